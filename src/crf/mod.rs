@@ -0,0 +1,7 @@
+mod templates;
+mod model;
+mod trainer;
+
+pub use templates::*;
+pub use model::*;
+pub use trainer::*;
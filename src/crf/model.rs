@@ -0,0 +1,206 @@
+use super::templates::{token_features, FeatureTemplate};
+use crate::linear::{self, emission_scores, viterbi, words_and_tokens};
+use crate::nlp::{Normalizer, TaggedSentence};
+use crate::Tagger;
+use std::{error::Error, collections::HashMap, path::PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// The log partition function of a sentence along with the posterior
+/// marginals of every (word, tag) and the expected number of times every
+/// transition is taken, laid out like the transition weights.
+pub(in crate::crf) struct Marginals {
+    pub log_partition: f64,
+    pub tags: Vec<f64>,
+    pub transitions: Vec<f64>
+}
+
+/// Runs the forward-backward algorithm over exponentiated potentials,
+/// rescaling each column to sum to one so that long sentences do not
+/// underflow.
+pub(in crate::crf) fn forward_backward(emissions: &[f64], transitions: &[f64], num_tags: usize) -> Marginals {
+    let length = emissions.len() / num_tags;
+    let start = num_tags * num_tags;
+    if length == 0 {
+        return Marginals { log_partition: 0.0, tags: Vec::new(), transitions: vec![0.0; transitions.len()] };
+    }
+
+    let mut log_partition = 0.0;
+    let mut potentials = Vec::with_capacity(emissions.len());
+    for column in emissions.chunks(num_tags) {
+        let max = column.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        potentials.extend(column.iter().map(|s| (s - max).exp()));
+        log_partition += max;
+    }
+
+    let max_transition = transitions.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let transition_potentials: Vec<f64> = transitions
+        .iter()
+        .map(|s| (s - max_transition).exp())
+        .collect();
+    log_partition += max_transition * length as f64;
+
+    let mut alpha = vec![0.0; emissions.len()];
+    let mut scales = vec![0.0; length];
+    for time in 0..length {
+        for tag in 0..num_tags {
+            let incoming = if time == 0 {
+                transition_potentials[start + tag]
+            } else {
+                (0..num_tags)
+                    .map(|prev| alpha[(time - 1) * num_tags + prev] * transition_potentials[prev * num_tags + tag])
+                    .sum()
+            };
+            alpha[time * num_tags + tag] = incoming * potentials[time * num_tags + tag];
+        }
+
+        let column = &mut alpha[time * num_tags..(time + 1) * num_tags];
+        scales[time] = column.iter().sum();
+        column.iter_mut().for_each(|a| *a /= scales[time]);
+        log_partition += scales[time].ln();
+    }
+
+    let mut beta = vec![1.0; emissions.len()];
+    for time in (0..length.saturating_sub(1)).rev() {
+        for prev in 0..num_tags {
+            beta[time * num_tags + prev] = (0..num_tags)
+                .map(|tag| {
+                    let next = (time + 1) * num_tags + tag;
+                    transition_potentials[prev * num_tags + tag] * potentials[next] * beta[next]
+                })
+                .sum::<f64>() / scales[time + 1];
+        }
+    }
+
+    let tags: Vec<f64> = alpha.iter().zip(beta.iter()).map(|(a, b)| a * b).collect();
+    let mut expected_transitions = vec![0.0; transitions.len()];
+    expected_transitions[start..].copy_from_slice(&tags[..num_tags]);
+    for time in 1..length {
+        for prev in 0..num_tags {
+            let a = alpha[(time - 1) * num_tags + prev];
+            for tag in 0..num_tags {
+                let next = time * num_tags + tag;
+                expected_transitions[prev * num_tags + tag] +=
+                    a * transition_potentials[prev * num_tags + tag] * potentials[next] * beta[next] / scales[time];
+            }
+        }
+    }
+
+    Marginals { log_partition, tags, transitions: expected_transitions }
+}
+
+/// A first-order linear-chain conditional random field over the features
+/// produced by a set of templates.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CRFTagger {
+    templates: Vec<FeatureTemplate>,
+    tag_set: Vec<String>,
+    feature_indices: HashMap<String, usize>,
+    weights: Vec<f64>,
+    transitions: Vec<f64>,
+    normalizer: Normalizer
+}
+
+impl CRFTagger {
+    pub const FILE_HEADER: [u8; 4] = *b"VCRF";
+
+    pub(in crate::crf) fn new(
+        templates: Vec<FeatureTemplate>,
+        tag_set: Vec<String>,
+        feature_indices: HashMap<String, usize>,
+        weights: Vec<f64>,
+        transitions: Vec<f64>,
+        normalizer: Normalizer
+    ) -> Self {
+        Self { templates, tag_set, feature_indices, weights, transitions, normalizer }
+    }
+
+    pub fn templates(&self) -> &[FeatureTemplate] {
+        &self.templates
+    }
+
+    pub fn tag_set(&self) -> &[String] {
+        &self.tag_set
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.feature_indices.len()
+    }
+
+    /// How words are normalized before the templates read them.
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        linear::from_file(Self::FILE_HEADER, path)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        linear::save(self, Self::FILE_HEADER, path)
+    }
+
+    pub fn predict(&self, sentence: Vec<String>) -> TaggedSentence {
+        let tags = viterbi(&self.weights, &self.transitions, self.tag_set.len(), &self.features(&sentence));
+
+        sentence
+            .into_iter()
+            .zip(tags)
+            .map(|(word, tag)| (word, self.tag_set[tag].clone()))
+            .collect()
+    }
+
+    /// The posterior distribution over tags of every word of the sentence.
+    pub fn posteriors(&self, sentence: Vec<String>) -> Vec<(String, Vec<f64>)> {
+        let emissions = emission_scores(&self.weights, self.tag_set.len(), &self.features(&sentence));
+        let marginals = forward_backward(&emissions, &self.transitions, self.tag_set.len());
+
+        sentence
+            .into_iter()
+            .zip(marginals.tags.chunks(self.tag_set.len()).map(<[f64]>::to_vec))
+            .collect()
+    }
+
+    fn features(&self, sentence: &[String]) -> Vec<Vec<usize>> {
+        let (words, tokens) = words_and_tokens(&self.normalizer, sentence);
+        (0..sentence.len())
+            .map(|i| {
+                token_features(&self.templates, &words, &tokens, i)
+                    .iter()
+                    .filter_map(|f| self.feature_indices.get(f).copied())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+impl Tagger for CRFTagger {
+    /// Scores every tag with its posterior probability.
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        let num_tags = self.tag_set.len();
+        let features = self.features(sentence);
+        let tags = viterbi(&self.weights, &self.transitions, num_tags, &features);
+        let emissions = emission_scores(&self.weights, num_tags, &features);
+        let marginals = forward_backward(&emissions, &self.transitions, num_tags);
+
+        let scores = tags
+            .iter()
+            .enumerate()
+            .map(|(time, &tag)| marginals.tags[time * num_tags + tag])
+            .collect();
+        let tags = tags
+            .into_iter()
+            .map(|tag| self.tag_set[tag].clone())
+            .collect();
+
+        (tags, Some(scores))
+    }
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        let posteriors = self.posteriors(sentence.to_vec())
+            .into_iter()
+            .map(|(_, marginals)| self.tag_set.iter().cloned().zip(marginals).collect())
+            .collect();
+
+        Some(posteriors)
+    }
+}
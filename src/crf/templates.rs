@@ -0,0 +1,112 @@
+use crate::nlp::{get_matching_affix_tags, get_word_shape};
+use crate::linear::{neighbour, prefix, suffix};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+pub const DEFAULT_FEATURE_TEMPLATES: &str =
+    "bias,w[0],w[-1],w[1],w[-2],w[2],pre1[0],suf1[0],suf2[0],suf3[0],shape[0],affix[0]";
+
+/// Describes one family of features by the attribute it reads and the
+/// position of the word it reads it from, relative to the word being tagged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeatureTemplate {
+    Bias,
+    Word(isize),
+    Prefix(isize, usize),
+    Suffix(isize, usize),
+    Shape(isize),
+    Affixes(isize)
+}
+
+impl FeatureTemplate {
+    pub fn default_templates() -> Vec<Self> {
+        DEFAULT_FEATURE_TEMPLATES
+            .split(',')
+            .map(|t| t.parse().unwrap())
+            .collect()
+    }
+
+    /// Adds the features this template produces for the word at `index`.
+    /// Word templates read the case-folded `words`, while spelling templates
+    /// read the `tokens` as written.
+    pub(in crate::crf) fn extract(&self, words: &[String], tokens: &[String], index: usize, features: &mut Vec<String>) {
+        let word = |offset: isize| neighbour(words, index, offset);
+        let token = |offset: isize| neighbour(tokens, index, offset);
+
+        match *self {
+            Self::Bias => features.push(self.to_string()),
+            Self::Word(offset) => features.push(format!("{self}={}", word(offset))),
+            Self::Prefix(offset, length) => features.push(format!("{self}={}", prefix(token(offset), length))),
+            Self::Suffix(offset, length) => features.push(format!("{self}={}", suffix(token(offset), length))),
+            Self::Shape(offset) => features.push(format!("{self}={}", get_word_shape(token(offset)))),
+            Self::Affixes(offset) => features.extend(
+                get_matching_affix_tags(token(offset))
+                    .into_iter()
+                    .map(|tag| format!("{self}={tag}"))
+            )
+        }
+    }
+}
+
+impl fmt::Display for FeatureTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bias => write!(f, "bias"),
+            Self::Word(offset) => write!(f, "w[{offset}]"),
+            Self::Prefix(offset, length) => write!(f, "pre{length}[{offset}]"),
+            Self::Suffix(offset, length) => write!(f, "suf{length}[{offset}]"),
+            Self::Shape(offset) => write!(f, "shape[{offset}]"),
+            Self::Affixes(offset) => write!(f, "affix[{offset}]")
+        }
+    }
+}
+
+/// Parses templates written as `bias`, `w[OFFSET]`, `preN[OFFSET]`,
+/// `sufN[OFFSET]`, `shape[OFFSET]` or `affix[OFFSET]`.
+impl FromStr for FeatureTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s == "bias" {
+            return Ok(Self::Bias);
+        }
+
+        let invalid = || format!("Invalid feature template {s}");
+        let (name, offset) = s
+            .strip_suffix(']')
+            .and_then(|s| s.split_once('['))
+            .ok_or_else(invalid)?;
+        let offset: isize = offset.parse().map_err(|_| invalid())?;
+        let length = |digits: &str| match digits.parse::<usize>() {
+            Ok(length) if length > 0 => Ok(length),
+            _ => Err(invalid())
+        };
+
+        match name {
+            "w" => Ok(Self::Word(offset)),
+            "shape" => Ok(Self::Shape(offset)),
+            "affix" => Ok(Self::Affixes(offset)),
+            _ => match (name.strip_prefix("pre"), name.strip_prefix("suf")) {
+                (Some(digits), _) => Ok(Self::Prefix(offset, length(digits)?)),
+                (_, Some(digits)) => Ok(Self::Suffix(offset, length(digits)?)),
+                _ => Err(invalid())
+            }
+        }
+    }
+}
+
+/// The features every template produces for the word at `index`.
+pub(in crate::crf) fn token_features(
+    templates: &[FeatureTemplate],
+    words: &[String],
+    tokens: &[String],
+    index: usize
+) -> Vec<String> {
+    let mut features = Vec::new();
+    for template in templates {
+        template.extract(words, tokens, index, &mut features);
+    }
+
+    features
+}
@@ -0,0 +1,182 @@
+use super::model::{forward_backward, CRFTagger};
+use super::templates::{token_features, FeatureTemplate};
+use crate::linear::{emission_scores, LearningRule, LinearTrainer, TrainingSet};
+use std::error::Error;
+
+const DEFAULT_EPOCHS: usize = 10;
+pub const DEFAULT_LEARNING_RATE: f64 = 0.5;
+pub const DEFAULT_L2: f64 = 0.1;
+
+/// Rescale the weights once the shared regularization factor gets this small
+/// to keep the scaled updates accurate.
+const MIN_WEIGHT_SCALE: f64 = 1e-9;
+
+/// Weights stored as a shared scale times per-weight values so that the L2
+/// decay applied at every step does not have to touch every weight.
+struct ScaledWeights {
+    scale: f64,
+    values: Vec<f64>
+}
+
+impl ScaledWeights {
+    fn new(len: usize) -> Self {
+        Self { scale: 1.0, values: vec![0.0; len] }
+    }
+
+    fn decay(&mut self, factor: f64) {
+        self.scale *= factor;
+        if self.scale < MIN_WEIGHT_SCALE {
+            self.values.iter_mut().for_each(|v| *v *= self.scale);
+            self.scale = 1.0;
+        }
+    }
+
+    fn add(&mut self, index: usize, delta: f64) {
+        self.values[index] += delta / self.scale;
+    }
+
+    fn squared_norm(&self) -> f64 {
+        self.values.iter().map(|v| v * v).sum::<f64>() * self.scale * self.scale
+    }
+
+    fn into_weights(self) -> Vec<f64> {
+        self.values.into_iter().map(|v| v * self.scale).collect()
+    }
+}
+
+/// Stochastic gradient descent on the L2-regularized conditional
+/// log-likelihood of the training sentences, over the features produced by
+/// a set of templates.
+#[derive(Debug)]
+pub struct CRFRule {
+    templates: Vec<FeatureTemplate>,
+    epochs: usize,
+    learning_rate: f64,
+    l2: f64
+}
+
+impl Default for CRFRule {
+    fn default() -> Self {
+        Self {
+            templates: FeatureTemplate::default_templates(),
+            epochs: DEFAULT_EPOCHS,
+            learning_rate: DEFAULT_LEARNING_RATE,
+            l2: DEFAULT_L2
+        }
+    }
+}
+
+impl LearningRule for CRFRule {
+    fn token_features(&self, words: &[String], tokens: &[String], index: usize) -> Vec<String> {
+        token_features(&self.templates, words, tokens, index)
+    }
+}
+
+/// Trains a `CRFTagger` by stochastic gradient descent on the L2-regularized
+/// conditional log-likelihood of the training sentences.
+pub type CRFTrainer = LinearTrainer<CRFRule>;
+
+impl CRFTrainer {
+    pub fn with_templates(mut self, templates: Vec<FeatureTemplate>) -> Self {
+        self.rule_mut().templates = templates;
+        self
+    }
+
+    pub fn with_epochs(mut self, epochs: usize) -> Self {
+        self.rule_mut().epochs = epochs;
+        self
+    }
+
+    /// Sets the initial step size, which decays as 1 / (1 + epoch).
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.rule_mut().learning_rate = learning_rate;
+        self
+    }
+
+    /// Sets the strength of the L2 penalty on the weights.
+    pub fn with_l2(mut self, l2: f64) -> Self {
+        self.rule_mut().l2 = l2;
+        self
+    }
+
+    pub fn finalize(self) -> Result<CRFTagger, Box<dyn Error>> {
+        self.finalize_with_progress(|_, _| ())
+    }
+
+    /// Trains the model, reporting the regularized log-likelihood of the
+    /// training sentences after every epoch.
+    pub fn finalize_with_progress(self, mut on_epoch: impl FnMut(usize, f64)) -> Result<CRFTagger, Box<dyn Error>> {
+        let (rule, TrainingSet { tag_set, feature_indices, examples, normalizer }) = self.into_training_set()?;
+
+        // Every example decays the weights by 1 - rate * l2 / n, which must
+        // stay positive for the weights to keep their signs.
+        if !(rule.learning_rate > 0.0 && rule.learning_rate.is_finite()) {
+            Err("Expected a positive learning rate")?
+        }
+        if !(rule.l2 >= 0.0 && rule.l2.is_finite()) {
+            Err("Expected a non-negative L2 penalty")?
+        }
+        if rule.learning_rate * rule.l2 >= examples.len() as f64 {
+            Err("Expected the learning rate times the L2 penalty to be less than the number of training sentences")?
+        }
+
+        let num_tags = tag_set.len();
+        let mut weights = ScaledWeights::new(feature_indices.len() * num_tags);
+        let mut transitions = ScaledWeights::new((num_tags + 1) * num_tags);
+        let decay_per_example = rule.l2 / examples.len() as f64;
+
+        for epoch in 0..rule.epochs {
+            let rate = rule.learning_rate / (1.0 + epoch as f64);
+            let mut log_likelihood = 0.0;
+
+            for example in examples.iter() {
+                weights.decay(1.0 - rate * decay_per_example);
+                transitions.decay(1.0 - rate * decay_per_example);
+
+                let mut emissions = emission_scores(&weights.values, num_tags, &example.features);
+                emissions.iter_mut().for_each(|s| *s *= weights.scale);
+                let transition_scores: Vec<f64> = transitions.values
+                    .iter()
+                    .map(|t| t * transitions.scale)
+                    .collect();
+                let marginals = forward_backward(&emissions, &transition_scores, num_tags);
+
+                let mut previous = num_tags;
+                for (time, &tag) in example.tags.iter().enumerate() {
+                    let transition = previous * num_tags + tag;
+                    log_likelihood += emissions[time * num_tags + tag] + transition_scores[transition];
+                    transitions.add(transition, rate);
+                    previous = tag;
+                }
+                log_likelihood -= marginals.log_partition;
+
+                for (index, expected) in marginals.transitions.iter().enumerate() {
+                    transitions.add(index, -rate * expected);
+                }
+
+                for (time, features) in example.features.iter().enumerate() {
+                    let gold = example.tags[time];
+                    let posteriors = &marginals.tags[time * num_tags..(time + 1) * num_tags];
+                    for &f in features {
+                        weights.add(f * num_tags + gold, rate);
+                        for (tag, posterior) in posteriors.iter().enumerate() {
+                            weights.add(f * num_tags + tag, -rate * posterior);
+                        }
+                    }
+                }
+            }
+
+            let penalty = 0.5 * rule.l2 * (weights.squared_norm() + transitions.squared_norm());
+            on_epoch(epoch + 1, log_likelihood - penalty);
+        }
+
+        Ok(CRFTagger::new(
+            rule.templates,
+            tag_set,
+            feature_indices,
+            weights.into_weights(),
+            transitions.into_weights(),
+            normalizer
+        ))
+    }
+}
@@ -0,0 +1,161 @@
+use crate::Tagger;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Probability given to a tag that a member's posteriors leave out, so that
+/// one member cannot veto a tag by itself.
+const MIN_POSTERIOR: f64 = 1e-10;
+
+/// The tags of a sentence along with their scores, as `Tagger::tag` returns.
+pub type Tagging = (Vec<String>, Option<Vec<f64>>);
+
+/// How the members of an ensemble settle on the tag of each token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Vote {
+    /// Every member casts one vote for its tag.
+    Majority,
+    /// Every member casts a vote with the weight at its position.
+    Weighted(Vec<f64>),
+    /// Sum the log-posteriors of each tag across members. Members without
+    /// posteriors are left out, and vote by majority if none has any.
+    LogPosterior
+}
+
+/// Tags sentences by combining the taggings of several models. Tied votes
+/// go to the tag of the earliest member.
+pub struct Ensemble<T> {
+    members: Vec<T>,
+    vote: Vote
+}
+
+impl<T: Tagger> Ensemble<T> {
+    pub fn new(members: Vec<T>, vote: Vote) -> Result<Self, Box<dyn Error>> {
+        if members.is_empty() {
+            Err("An ensemble needs at least one model")?
+        }
+
+        if let Vote::Weighted(weights) = &vote {
+            if weights.len() != members.len() {
+                Err("Expected exactly one weight per model")?
+            }
+            if !weights.iter().all(|w| w.is_finite() && *w > 0.0) {
+                Err("Expected every weight to be positive")?
+            }
+        }
+
+        Ok(Self { members, vote })
+    }
+
+    pub fn members_mut(&mut self) -> &mut [T] {
+        &mut self.members
+    }
+
+    /// Tags `sentence` like `Tagger::tag`, but fails if a member does not
+    /// tag every token of it.
+    pub fn try_tag(&self, sentence: &[String]) -> Result<Tagging, Box<dyn Error>> {
+        match &self.vote {
+            Vote::Majority => self.vote(sentence, &vec![1.0; self.members.len()]),
+            Vote::Weighted(weights) => self.vote(sentence, weights),
+            Vote::LogPosterior => self.sum_log_posteriors(sentence)
+        }
+    }
+
+    fn vote(&self, sentence: &[String], weights: &[f64]) -> Result<Tagging, Box<dyn Error>> {
+        let taggings: Vec<Vec<String>> = self.members
+            .iter()
+            .map(|m| m.tag(sentence).0)
+            .collect();
+        if let Some(tagging) = taggings.iter().find(|t| t.len() != sentence.len()) {
+            Err(format!("A member predicted {} tags for a sentence of {} words", tagging.len(), sentence.len()))?
+        }
+        let total: f64 = weights.iter().sum();
+
+        let (tags, scores) = (0..sentence.len())
+            .map(|time| {
+                let mut ballots: Vec<(&str, f64)> = Vec::new();
+                for (tagging, weight) in taggings.iter().zip(weights) {
+                    let tag = tagging[time].as_str();
+                    match ballots.iter_mut().find(|(t, _)| *t == tag) {
+                        Some((_, votes)) => *votes += weight,
+                        None => ballots.push((tag, *weight))
+                    }
+                }
+
+                let (tag, votes) = best(ballots);
+                (tag.to_string(), votes / total)
+            })
+            .unzip();
+
+        Ok((tags, Some(scores)))
+    }
+
+    fn sum_log_posteriors(&self, sentence: &[String]) -> Result<Tagging, Box<dyn Error>> {
+        let posteriors: Vec<Vec<HashMap<String, f64>>> = self.members
+            .iter()
+            .filter_map(|m| m.tag_posteriors(sentence))
+            .collect();
+        if posteriors.is_empty() {
+            return self.vote(sentence, &vec![1.0; self.members.len()]);
+        }
+        if let Some(member) = posteriors.iter().find(|p| p.len() != sentence.len()) {
+            Err(format!("A member gave posteriors for {} tokens of a sentence of {} words", member.len(), sentence.len()))?
+        }
+
+        let (tags, scores) = (0..sentence.len())
+            .map(|time| {
+                // Take each member's tags from most to least likely so that
+                // tied tags go to the preference of the earliest member.
+                let mut candidates: Vec<&str> = Vec::new();
+                for member in posteriors.iter() {
+                    let mut tags: Vec<(&String, &f64)> = member[time].iter().collect();
+                    tags.sort_by(|(t1, p1), (t2, p2)| p2.total_cmp(p1).then(t1.cmp(t2)));
+                    for (tag, _) in tags {
+                        if !candidates.contains(&tag.as_str()) {
+                            candidates.push(tag);
+                        }
+                    }
+                }
+
+                let scores: Vec<(&str, f64)> = candidates
+                    .into_iter()
+                    .map(|tag| {
+                        let score = posteriors
+                            .iter()
+                            .map(|member| member[time].get(tag).copied().unwrap_or(0.0).max(MIN_POSTERIOR).ln())
+                            .sum();
+                        (tag, score)
+                    })
+                    .collect();
+
+                let normalizer = log_sum_exp(scores.iter().map(|(_, s)| *s));
+                let (tag, score) = best(scores);
+                (tag.to_string(), (score - normalizer).exp())
+            })
+            .unzip();
+
+        Ok((tags, Some(scores)))
+    }
+}
+
+impl<T: Tagger> Tagger for Ensemble<T> {
+    /// Scores every tag with the share of the vote, or with its normalized
+    /// posterior under the product of the members' posteriors. Leaves the
+    /// sentence untagged if a member does not tag every token, which
+    /// evaluation reports as an error and `try_tag` explains.
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        self.try_tag(sentence).unwrap_or_default()
+    }
+}
+
+/// Picks the highest-scoring candidate, keeping the earliest on ties.
+fn best(candidates: Vec<(&str, f64)>) -> (&str, f64) {
+    candidates
+        .into_iter()
+        .reduce(|best, candidate| if candidate.1 > best.1 { candidate } else { best })
+        .unwrap()
+}
+
+fn log_sum_exp(scores: impl Iterator<Item = f64> + Clone) -> f64 {
+    let max = scores.clone().fold(f64::NEG_INFINITY, f64::max);
+    max + scores.map(|s| (s - max).exp()).sum::<f64>().ln()
+}
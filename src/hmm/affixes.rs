@@ -0,0 +1,159 @@
+use crate::nlp::get_matching_shape_tag;
+use crate::{StringCounter, ConditionalStringCounter};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The longest prefix or suffix considered as an affix class.
+const MAX_AFFIX_LENGTH: usize = 4;
+
+/// Affixes shared by fewer hapax words than this are never chosen.
+const MIN_AFFIX_COUNT: usize = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Affix {
+    Prefix(String),
+    Suffix(String)
+}
+
+impl Affix {
+    fn len(&self) -> usize {
+        match self {
+            Affix::Prefix(affix) | Affix::Suffix(affix) => affix.chars().count()
+        }
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        match self {
+            Affix::Prefix(prefix) => word.len() > prefix.len() && word.starts_with(prefix.as_str()),
+            Affix::Suffix(suffix) => word.len() > suffix.len() && word.ends_with(suffix.as_str())
+        }
+    }
+
+    /// The pseudo-word standing in for unknown words with this affix, named
+    /// like the hard-coded ones.
+    fn artificial_tag(&self) -> String {
+        match self {
+            Affix::Prefix(prefix) => format!("PREF-{}", prefix.to_uppercase()),
+            Affix::Suffix(suffix) => format!("SUFF-{}", suffix.to_uppercase())
+        }
+    }
+
+    /// Every prefix and suffix of `word` short enough to be an affix class.
+    fn candidates(word: &str) -> Vec<Affix> {
+        let boundaries: Vec<usize> = word.char_indices().map(|(i, _)| i).skip(1).collect();
+        let prefixes = boundaries
+            .iter()
+            .take(MAX_AFFIX_LENGTH)
+            .map(|&i| Affix::Prefix(word[..i].to_string()));
+        let suffixes = boundaries
+            .iter()
+            .rev()
+            .take(MAX_AFFIX_LENGTH)
+            .map(|&i| Affix::Suffix(word[i..].to_string()));
+
+        prefixes.chain(suffixes).collect()
+    }
+}
+
+fn entropy(counts: &HashMap<&str, usize>) -> f64 {
+    let total: usize = counts.values().sum();
+    counts
+        .values()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.ln()
+        })
+        .sum()
+}
+
+/// Prefixes and suffixes learned from the hapax words seen in training,
+/// ranked by how much they tell about the tag of a word.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AffixClasses {
+    classes: Vec<(Affix, String)>
+}
+
+impl AffixClasses {
+    /// Greedily picks up to `max_classes` affixes, each time choosing the one
+    /// with the most information gain about the tags of the hapax words that
+    /// no earlier affix matched.
+    pub(in crate::hmm) fn learn(emission_counts: &ConditionalStringCounter, max_classes: usize) -> Self {
+        let mut word_counts = StringCounter::new();
+        for counts in emission_counts.values() {
+            word_counts.add_all(counts.clone());
+        }
+
+        let mut hapaxes: Vec<(&str, &str)> = emission_counts
+            .iter()
+            .flat_map(|(tag, counts)| {
+                counts
+                    .keys()
+                    .filter(|word| word_counts.get(word) == 1)
+                    .map(move |word| (word.as_str(), tag.as_str()))
+            })
+            .collect();
+        hapaxes.sort_unstable();
+
+        let mut classes = Vec::new();
+        while classes.len() < max_classes {
+            let mut tag_counts: HashMap<&str, usize> = HashMap::new();
+            let mut affix_tag_counts: BTreeMap<Affix, HashMap<&str, usize>> = BTreeMap::new();
+            for &(word, tag) in hapaxes.iter() {
+                *tag_counts.entry(tag).or_default() += 1;
+                for affix in Affix::candidates(word) {
+                    *affix_tag_counts.entry(affix).or_default().entry(tag).or_default() += 1;
+                }
+            }
+
+            let total = hapaxes.len() as f64;
+            let prior_entropy = entropy(&tag_counts);
+            let best = affix_tag_counts
+                .into_iter()
+                .filter(|(_, counts)| counts.values().sum::<usize>() >= MIN_AFFIX_COUNT)
+                .map(|(affix, matching)| {
+                    let rest: HashMap<&str, usize> = tag_counts
+                        .iter()
+                        .map(|(&tag, &count)| (tag, count - matching.get(tag).copied().unwrap_or(0)))
+                        .collect();
+                    let share = matching.values().sum::<usize>() as f64 / total;
+                    let gain = prior_entropy - share * entropy(&matching) - (1.0 - share) * entropy(&rest);
+                    (affix, gain)
+                })
+                .fold(None, |best: Option<(Affix, f64)>, (affix, gain)| match best {
+                    // Prefer the shorter of two equally informative affixes
+                    // since it matches more unknown words.
+                    Some((best_affix, best_gain))
+                        if best_gain > gain || (best_gain == gain && best_affix.len() <= affix.len()) => {
+                        Some((best_affix, best_gain))
+                    },
+                    _ => Some((affix, gain))
+                });
+
+            match best {
+                Some((affix, gain)) if gain > 0.0 => {
+                    hapaxes.retain(|(word, _)| !affix.matches(word));
+                    let tag = affix.artificial_tag();
+                    classes.push((affix, tag));
+                },
+                _ => break
+            }
+        }
+
+        Self { classes }
+    }
+
+    pub fn affixes(&self) -> impl Iterator<Item=&Affix> {
+        self.classes.iter().map(|(affix, _)| affix)
+    }
+
+    /// The artificial tag of the highest-ranked affix of `word`, falling back
+    /// to the tags of numbers and dashed words.
+    pub fn matching_artificial_tag(&self, word: &str) -> Option<&str> {
+        self.classes
+            .iter()
+            .find(|(affix, _)| affix.matches(word))
+            .map(|(_, tag)| tag.as_str())
+            .or_else(|| get_matching_shape_tag(word))
+    }
+}
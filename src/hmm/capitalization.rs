@@ -0,0 +1,41 @@
+use crate::{ConditionalStringCounter, ConditionalStringFrequencyDistribution};
+use crate::nlp::Capitalization;
+use serde::{Deserialize, Serialize};
+
+/// How likely each tag is to be written with each capitalization, both over
+/// every word seen in training and over the hapax words that stand in for
+/// unknown ones.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CapitalizationModel {
+    known: ConditionalStringFrequencyDistribution,
+    unknown: ConditionalStringFrequencyDistribution
+}
+
+impl CapitalizationModel {
+    pub(in crate::hmm) fn estimate(tag_counts: ConditionalStringCounter, hapax_tag_counts: ConditionalStringCounter) -> Self {
+        Self {
+            known: ConditionalStringFrequencyDistribution::with_default_smoothing(tag_counts),
+            unknown: ConditionalStringFrequencyDistribution::with_default_smoothing(hapax_tag_counts)
+        }
+    }
+
+    /// The log-likelihood of `capitalization` under every tag of `tag_set`.
+    /// Tags never seen with an unknown word fall back to their likelihoods
+    /// over all words.
+    pub fn log_likelihoods(&self, capitalization: Capitalization, is_known: bool, tag_set: &[String]) -> Vec<f64> {
+        let capitalization = capitalization.as_str();
+        tag_set
+            .iter()
+            .map(|tag| {
+                let likelihood = match is_known {
+                    true => None,
+                    false => self.unknown.get_likelihood(tag, capitalization)
+                };
+
+                likelihood
+                    .or_else(|| self.known.get_likelihood(tag, capitalization))
+                    .unwrap_or(0.0)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{StringFrequencyDistribution, ConditionalStringFrequencyDistribution};
+use std::collections::HashMap;
+
+/// Integer-indexed copies of the model distributions used while decoding.
+/// Tags are indexed by their position in the model's tag set and every word
+/// seen in training owns a contiguous row of emission scores, one per tag.
+/// Transitions are stored by current tag so that the scores into a tag from
+/// every previous tag are contiguous.
+#[derive(Debug, Default)]
+pub(in crate::hmm) struct DenseModel {
+    num_tags: usize,
+    initial: Vec<f64>,
+    transitions: Vec<f64>,
+    vocabulary: HashMap<String, usize>,
+    emissions: Vec<f64>,
+    unseen_emissions: Vec<f64>
+}
+
+impl DenseModel {
+    pub fn compile(
+        tag_set: &[String],
+        initial_tag_distribution: &StringFrequencyDistribution,
+        emission_distribution: &ConditionalStringFrequencyDistribution,
+        transition_distribution: &ConditionalStringFrequencyDistribution
+    ) -> Self {
+        let num_tags = tag_set.len();
+        let initial = tag_set
+            .iter()
+            .map(|tag| initial_tag_distribution.get_likelihood(tag))
+            .collect();
+
+        let transitions = tag_set
+            .iter()
+            .flat_map(|curr_tag| {
+                tag_set.iter().map(move |prev_tag| {
+                    transition_distribution
+                        .get_likelihood(prev_tag, curr_tag)
+                        .unwrap_or(f64::NEG_INFINITY)
+                })
+            })
+            .collect();
+
+        let tag_emissions: Vec<Option<&StringFrequencyDistribution>> = tag_set
+            .iter()
+            .map(|tag| emission_distribution.get(tag))
+            .collect();
+
+        let unseen_emissions: Vec<f64> = tag_emissions
+            .iter()
+            .map(|d| d.map_or(f64::NEG_INFINITY, StringFrequencyDistribution::smoothed_default))
+            .collect();
+
+        let mut vocabulary = HashMap::new();
+        let mut emissions = Vec::new();
+        for (tag_index, distribution) in tag_emissions.iter().enumerate() {
+            for (word, &likelihood) in distribution.iter().flat_map(|d| d.iter()) {
+                let row = *vocabulary.entry(word.clone()).or_insert_with(|| {
+                    emissions.extend_from_slice(&unseen_emissions);
+                    emissions.len() / num_tags - 1
+                });
+                emissions[row * num_tags + tag_index] = likelihood;
+            }
+        }
+
+        Self { num_tags, initial, transitions, vocabulary, emissions, unseen_emissions }
+    }
+
+    pub fn initial(&self) -> &[f64] {
+        &self.initial
+    }
+
+    pub fn transitions(&self) -> &[f64] {
+        &self.transitions
+    }
+
+    pub fn contains_word(&self, word: &str) -> bool {
+        self.vocabulary.contains_key(word)
+    }
+
+    pub fn emission_row(&self, word: &str) -> &[f64] {
+        match self.vocabulary.get(word) {
+            Some(&row) => &self.emissions[row * self.num_tags..(row + 1) * self.num_tags],
+            None => &self.unseen_emissions
+        }
+    }
+}
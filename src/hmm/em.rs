@@ -0,0 +1,72 @@
+use super::model::ExpectedCounts;
+use super::read_untagged_sentences;
+use crate::{POSTaggingHMM, POSTaggingHMMTrainer, HMMOrder};
+use std::path::PathBuf;
+use std::error::Error;
+
+/// Re-estimates a first-order model on untagged sentences with the
+/// Baum-Welch (expectation maximization) algorithm.
+pub struct BaumWelchTrainer {
+    model: POSTaggingHMM,
+    sentences: Vec<Vec<String>>
+}
+
+impl BaumWelchTrainer {
+    pub fn new(model: POSTaggingHMM) -> Result<Self, Box<dyn Error>> {
+        if model.order() != HMMOrder::First {
+            Err("Baum-Welch re-estimation only supports first-order models")?
+        }
+
+        Ok(Self { model, sentences: Vec::new() })
+    }
+
+    pub fn from_trainer(trainer: POSTaggingHMMTrainer) -> Result<Self, Box<dyn Error>> {
+        Self::new(trainer.finalize()?)
+    }
+
+    pub fn train(mut self, data_file: PathBuf) -> Result<Self, Box<dyn Error>> {
+        self.sentences.extend(read_untagged_sentences(data_file)?);
+        Ok(self)
+    }
+
+    /// Runs a single expectation maximization step and returns the
+    /// log-likelihood of the untagged sentences under the model it replaced.
+    pub fn iterate(&mut self) -> f64 {
+        let mut counts = ExpectedCounts::default();
+        for sentence in self.sentences.iter() {
+            self.model.accumulate_expectations(sentence.clone(), &mut counts);
+        }
+
+        let log_likelihood = counts.log_likelihood;
+        self.model = self.model.reestimate(counts);
+        log_likelihood
+    }
+
+    /// Iterates until `max_iterations` is reached or the relative change in
+    /// log-likelihood drops below `threshold`, reporting every iteration.
+    pub fn run(
+        mut self,
+        max_iterations: usize,
+        threshold: f64,
+        mut on_iteration: impl FnMut(usize, f64)
+    ) -> POSTaggingHMM {
+        let mut previous: Option<f64> = None;
+        for iteration in 1..=max_iterations {
+            let log_likelihood = self.iterate();
+            on_iteration(iteration, log_likelihood);
+
+            if let Some(previous) = previous {
+                if (log_likelihood - previous).abs() <= threshold * previous.abs() {
+                    break;
+                }
+            }
+            previous = Some(log_likelihood);
+        }
+
+        self.finalize()
+    }
+
+    pub fn finalize(self) -> POSTaggingHMM {
+        self.model
+    }
+}
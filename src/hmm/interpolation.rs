@@ -0,0 +1,124 @@
+use crate::{StringCounter, ConditionalStringCounter, ConditionalStringFrequencyDistribution};
+use crate::nlp::START_TAG;
+use serde::{Deserialize, Serialize};
+
+pub(in crate::hmm) fn tag_pair_key(first: &str, second: &str) -> String {
+    format!("{first} {second}")
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+fn discounted_ratio(numerator: usize, denominator: usize) -> f64 {
+    ratio(numerator.saturating_sub(1), denominator.saturating_sub(1))
+}
+
+/// Learns the unigram, bigram and trigram weights with the deleted
+/// interpolation algorithm from Brants (2000), "TnT -- A Statistical
+/// Part-of-Speech Tagger".
+fn deleted_interpolation(
+    unigram_counts: &StringCounter,
+    bigram_counts: &ConditionalStringCounter,
+    trigram_counts: &ConditionalStringCounter
+) -> [f64; 3] {
+    let num_tokens = unigram_counts.total();
+    let mut weights = [0.0; 3];
+
+    for (context, counts) in trigram_counts.iter() {
+        let (_, previous_tag) = context.split_once(' ').unwrap();
+        let context_total = counts.total();
+        let bigrams = bigram_counts.get(previous_tag);
+        let bigram_total = bigrams.map_or(0, StringCounter::total);
+
+        for (tag, &count) in counts.iter() {
+            let bigram_count = bigrams.map_or(0, |c| c.get(tag));
+            let candidates = [
+                discounted_ratio(unigram_counts.get(tag), num_tokens),
+                discounted_ratio(bigram_count, bigram_total),
+                discounted_ratio(count, context_total)
+            ];
+
+            let (best, _) = candidates
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            weights[best] += count as f64;
+        }
+    }
+
+    let total: f64 = weights.iter().sum();
+    if total == 0.0 {
+        return [1.0 / 3.0; 3];
+    }
+
+    weights.map(|w| w / total)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InterpolatedTransitions {
+    weights: [f64; 3],
+    num_tags: usize,
+    log_probabilities: Vec<f64>
+}
+
+impl InterpolatedTransitions {
+    /// Interpolates the unigram and trigram estimates with the bigram
+    /// estimates of `transitions`, so that the trigram transitions are
+    /// smoothed the way the bigram transitions are.
+    pub(in crate::hmm) fn estimate(
+        tag_set: &[String],
+        unigram_counts: &StringCounter,
+        bigram_counts: &ConditionalStringCounter,
+        trigram_counts: &ConditionalStringCounter,
+        transitions: &ConditionalStringFrequencyDistribution
+    ) -> Self {
+        let weights = deleted_interpolation(unigram_counts, bigram_counts, trigram_counts);
+        let num_tags = tag_set.len();
+        let unigram_total = unigram_counts.total();
+
+        let contexts: Vec<&str> = tag_set
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(START_TAG))
+            .collect();
+
+        // Laid out as [previous tag][tag][context] so that all of the
+        // contexts of a transition are contiguous while decoding.
+        let mut log_probabilities = Vec::with_capacity((num_tags + 1) * num_tags * num_tags);
+        for previous_tag in tag_set {
+            for tag in tag_set {
+                let unigram = ratio(unigram_counts.get(tag), unigram_total);
+                let bigram = transitions.get_likelihood(previous_tag, tag).unwrap();
+
+                for second_previous_tag in contexts.iter() {
+                    let trigrams = trigram_counts.get(&tag_pair_key(second_previous_tag, previous_tag));
+                    let trigram = trigrams.map_or(0.0, |c| ratio(c.get(tag), c.total()));
+                    let probability = weights[0] * unigram + weights[1] * bigram.exp() + weights[2] * trigram;
+
+                    // Fall back to the bigram estimate when its weight is
+                    // zero and the other components are unseen.
+                    log_probabilities.push(if probability > 0.0 { probability.ln() } else { bigram });
+                }
+            }
+        }
+
+        Self { weights, num_tags, log_probabilities }
+    }
+
+    pub fn weights(&self) -> [f64; 3] {
+        self.weights
+    }
+
+    /// Log-probabilities of `tag` following `previous_tag` for every
+    /// preceding tag, with the sentence start as the last entry.
+    pub(in crate::hmm) fn contexts(&self, previous_tag: usize, tag: usize) -> &[f64] {
+        let start = (previous_tag * self.num_tags + tag) * (self.num_tags + 1);
+        &self.log_probabilities[start..start + self.num_tags + 1]
+    }
+}
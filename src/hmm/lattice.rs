@@ -0,0 +1,414 @@
+use super::InterpolatedTransitions;
+use std::borrow::Cow;
+
+pub(in crate::hmm) enum Transitions<'a> {
+    FirstOrder(&'a [f64]),
+    SecondOrder(&'a InterpolatedTransitions)
+}
+
+/// The scores needed to decode a single sentence. First-order states are
+/// tags; second-order states are (tag, previous tag) pairs, where a previous
+/// tag equal to the number of tags marks the sentence start.
+pub(in crate::hmm) struct Lattice<'a> {
+    num_tags: usize,
+    initial: &'a [f64],
+    transitions: Transitions<'a>,
+    emissions: Vec<Cow<'a, [f64]>>
+}
+
+#[derive(Clone, Copy)]
+struct Backpointer {
+    score: f64,
+    state: usize,
+    rank: usize
+}
+
+impl<'a> Lattice<'a> {
+    pub fn new(initial: &'a [f64], transitions: Transitions<'a>, emissions: Vec<Cow<'a, [f64]>>) -> Self {
+        Self {
+            num_tags: initial.len(),
+            initial,
+            transitions,
+            emissions
+        }
+    }
+
+    /// Rules out every tag at `time` for which `allowed` returns false.
+    pub fn restrict(&mut self, time: usize, allowed: impl Fn(usize) -> bool) {
+        for (tag, emission) in self.emissions[time].to_mut().iter_mut().enumerate() {
+            if !allowed(tag) {
+                *emission = f64::NEG_INFINITY;
+            }
+        }
+    }
+
+    fn num_states(&self) -> usize {
+        match self.transitions {
+            Transitions::FirstOrder(_) => self.num_tags,
+            Transitions::SecondOrder(_) => (self.num_tags + 1) * self.num_tags
+        }
+    }
+
+    fn tag(&self, state: usize) -> usize {
+        match self.transitions {
+            Transitions::FirstOrder(_) => state,
+            Transitions::SecondOrder(_) => state / (self.num_tags + 1)
+        }
+    }
+
+    fn initial_score(&self, state: usize) -> f64 {
+        match self.transitions {
+            Transitions::FirstOrder(_) => self.initial[state],
+            Transitions::SecondOrder(_) if state % (self.num_tags + 1) == self.num_tags => {
+                self.initial[self.tag(state)]
+            },
+            Transitions::SecondOrder(_) => f64::NEG_INFINITY
+        }
+    }
+
+    fn emission(&self, time: usize, state: usize) -> f64 {
+        self.emissions[time][self.tag(state)]
+    }
+
+    /// Returns the first of the contiguous block of states that can precede
+    /// `state` along with the transition score from each of them.
+    fn predecessors(&self, state: usize) -> (usize, &'a [f64]) {
+        let n = self.num_tags;
+        match self.transitions {
+            Transitions::FirstOrder(transitions) => (0, &transitions[state * n..(state + 1) * n]),
+            Transitions::SecondOrder(transitions) => {
+                let (tag, previous_tag) = (state / (n + 1), state % (n + 1));
+                if previous_tag == n {
+                    return (0, &[]);
+                }
+
+                (previous_tag * (n + 1), transitions.contexts(previous_tag, tag))
+            }
+        }
+    }
+
+    fn for_each_predecessor(&self, state: usize, mut f: impl FnMut(usize, f64)) {
+        let (first, transitions) = self.predecessors(state);
+        for (offset, &transition) in transitions.iter().enumerate() {
+            f(first + offset, transition);
+        }
+    }
+
+    fn for_each_successor(&self, state: usize, mut f: impl FnMut(usize, f64)) {
+        let n = self.num_tags;
+        match self.transitions {
+            Transitions::FirstOrder(transitions) => {
+                for next in 0..n {
+                    f(next, transitions[next * n + state]);
+                }
+            },
+            Transitions::SecondOrder(transitions) => {
+                let (tag, previous_tag) = (state / (n + 1), state % (n + 1));
+                for next_tag in 0..n {
+                    f(next_tag * (n + 1) + tag, transitions.contexts(tag, next_tag)[previous_tag]);
+                }
+            }
+        }
+    }
+
+    fn initial_scores(&self) -> Vec<f64> {
+        (0..self.num_states())
+            .map(|s| self.initial_score(s) + self.emission(0, s))
+            .collect()
+    }
+
+    /// Returns the score and tag indices of the single best path.
+    pub fn viterbi(&self) -> (f64, Vec<usize>) {
+        let num_states = self.num_states();
+        let mut b = vec![vec![0; num_states]; self.emissions.len()];
+        let mut pv = self.initial_scores();
+
+        for (time, bt) in b.iter_mut().enumerate().skip(1) {
+            let cv = (0..num_states)
+                .map(|state| {
+                    let emission = self.emission(time, state);
+                    if emission == f64::NEG_INFINITY {
+                        return f64::NEG_INFINITY;
+                    }
+
+                    let (first, transitions) = self.predecessors(state);
+                    let mut best = (f64::NEG_INFINITY, first);
+                    for (offset, (score, transition)) in pv[first..].iter().zip(transitions).enumerate() {
+                        let score = score + transition;
+                        if score >= best.0 {
+                            best = (score, first + offset);
+                        }
+                    }
+
+                    bt[state] = best.1;
+                    best.0 + emission
+                })
+                .collect();
+            pv = cv;
+        }
+
+        let (best_state, best_score) = pv
+            .into_iter()
+            .enumerate()
+            .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+            .unwrap();
+
+        let mut states = vec![best_state; self.emissions.len()];
+        for time in (1..states.len()).rev() {
+            states[time - 1] = b[time][states[time]];
+        }
+
+        (best_score, states.into_iter().map(|s| self.tag(s)).collect())
+    }
+
+    /// Approximates the Viterbi path by only extending the `width` best
+    /// states at each column, and of those only the states scoring within
+    /// `threshold` of the best one.
+    pub fn beam(&self, width: usize, threshold: Option<f64>) -> Vec<usize> {
+        let num_states = self.num_states();
+        let mut b = vec![vec![0; num_states]; self.emissions.len()];
+
+        let initial = self.initial_scores();
+        let mut beam = prune(
+            (0..num_states).map(|s| (s, initial[s])).collect(),
+            width,
+            threshold
+        );
+
+        for (time, bt) in b.iter_mut().enumerate().skip(1) {
+            let mut cv = vec![f64::NEG_INFINITY; num_states];
+            let mut reached = Vec::new();
+            for &(previous, score) in beam.iter() {
+                self.for_each_successor(previous, |state, transition| {
+                    let score = score + transition;
+                    if cv[state] == f64::NEG_INFINITY {
+                        reached.push(state);
+                    }
+                    if score >= cv[state] {
+                        cv[state] = score;
+                        bt[state] = previous;
+                    }
+                });
+            }
+
+            let candidates = reached
+                .into_iter()
+                .map(|state| (state, cv[state] + self.emission(time, state)))
+                .collect();
+            beam = prune(candidates, width, threshold);
+        }
+
+        let mut states = vec![beam.first().map_or(0, |&(s, _)| s); self.emissions.len()];
+        for time in (1..states.len()).rev() {
+            states[time - 1] = b[time][states[time]];
+        }
+
+        states.into_iter().map(|s| self.tag(s)).collect()
+    }
+
+    /// Returns up to `k` distinct paths ordered from best to worst. The last
+    /// column is the sentence boundary, so paths only differing in its tag
+    /// are collapsed and the returned tags exclude it.
+    pub fn n_best(&self, k: usize) -> Vec<(f64, Vec<usize>)> {
+        let last = self.emissions.len() - 1;
+        if k == 0 || last == 0 {
+            let (score, _) = self.viterbi();
+            return (k > 0).then(|| (score, Vec::new())).into_iter().collect();
+        }
+
+        let num_states = self.num_states();
+        let mut trellis: Vec<Vec<Vec<Backpointer>>> = Vec::with_capacity(last);
+        trellis.push(
+            self.initial_scores()
+                .into_iter()
+                .map(|score| vec![Backpointer { score, state: 0, rank: 0 }])
+                .collect()
+        );
+
+        for time in 1..last {
+            let previous_cells = &trellis[time - 1];
+            let cells = (0..num_states)
+                .map(|state| {
+                    let emission = self.emission(time, state);
+                    let mut candidates = Vec::new();
+                    self.for_each_predecessor(state, |previous, transition| {
+                        for (rank, entry) in previous_cells[previous].iter().enumerate() {
+                            candidates.push(Backpointer {
+                                score: entry.score + transition + emission,
+                                state: previous,
+                                rank
+                            });
+                        }
+                    });
+
+                    keep_best(candidates, k)
+                })
+                .collect();
+            trellis.push(cells);
+        }
+
+        let mut boundary_scores = vec![f64::NEG_INFINITY; num_states];
+        for state in 0..num_states {
+            let emission = self.emission(last, state);
+            self.for_each_predecessor(state, |previous, transition| {
+                boundary_scores[previous] = boundary_scores[previous].max(transition + emission);
+            });
+        }
+
+        let finals = trellis[last - 1]
+            .iter()
+            .enumerate()
+            .flat_map(|(state, cell)| {
+                let boundary = boundary_scores[state];
+                cell.iter()
+                    .enumerate()
+                    .map(move |(rank, entry)| Backpointer { score: entry.score + boundary, state, rank })
+            })
+            .collect();
+
+        keep_best(finals, k)
+            .into_iter()
+            .filter(|end| end.score > f64::NEG_INFINITY)
+            .map(|end| {
+                let mut tags = vec![0; last];
+                let (mut state, mut rank) = (end.state, end.rank);
+                for time in (0..last).rev() {
+                    tags[time] = self.tag(state);
+                    let entry = trellis[time][state][rank];
+                    (state, rank) = (entry.state, entry.rank);
+                }
+
+                (end.score, tags)
+            })
+            .collect()
+    }
+
+    /// Runs the forward-backward algorithm, returning the forward and
+    /// backward log-scores of every state along with the sentence
+    /// log-likelihood.
+    fn forward_backward(&self) -> (Vec<Vec<f64>>, Vec<Vec<f64>>, f64) {
+        let num_states = self.num_states();
+        let len = self.emissions.len();
+
+        let mut scores = Vec::with_capacity(num_states);
+        let mut alpha = Vec::with_capacity(len);
+        alpha.push(self.initial_scores());
+        for time in 1..len {
+            let previous = &alpha[time - 1];
+            let column = (0..num_states)
+                .map(|state| {
+                    scores.clear();
+                    self.for_each_predecessor(state, |p, transition| scores.push(previous[p] + transition));
+                    log_sum_exp(&scores) + self.emission(time, state)
+                })
+                .collect();
+            alpha.push(column);
+        }
+
+        let mut beta = vec![vec![0.0; num_states]; len];
+        for time in (0..len - 1).rev() {
+            let (column, next) = beta.split_at_mut(time + 1);
+            for (state, backward) in column[time].iter_mut().enumerate() {
+                scores.clear();
+                self.for_each_successor(state, |s, transition| {
+                    scores.push(transition + self.emission(time + 1, s) + next[0][s]);
+                });
+                *backward = log_sum_exp(&scores);
+            }
+        }
+
+        let log_likelihood = log_sum_exp(&alpha[len - 1]);
+
+        (alpha, beta, log_likelihood)
+    }
+
+    /// Returns, for every column but the sentence boundary, the posterior
+    /// probability of each tag.
+    pub fn posteriors(&self) -> Vec<Vec<f64>> {
+        let (alpha, beta, log_likelihood) = self.forward_backward();
+        self.marginals(&alpha, &beta, log_likelihood)
+    }
+
+    fn marginals(&self, alpha: &[Vec<f64>], beta: &[Vec<f64>], log_likelihood: f64) -> Vec<Vec<f64>> {
+        let num_states = self.num_states();
+        (0..alpha.len() - 1)
+            .map(|time| {
+                let mut marginals = vec![0.0; self.num_tags];
+                for state in 0..num_states {
+                    let log_posterior = alpha[time][state] + beta[time][state] - log_likelihood;
+                    marginals[self.tag(state)] += log_posterior.exp();
+                }
+                marginals
+            })
+            .collect()
+    }
+
+    /// Returns the sentence log-likelihood, the tag posteriors of every column
+    /// but the sentence boundary, and the expected number of times each
+    /// (previous tag, tag) transition is taken between those columns.
+    pub fn expectations(&self) -> (f64, Vec<Vec<f64>>, Vec<f64>) {
+        let (alpha, beta, log_likelihood) = self.forward_backward();
+        let marginals = self.marginals(&alpha, &beta, log_likelihood);
+
+        let mut transitions = vec![0.0; self.num_tags * self.num_tags];
+        for time in 1..alpha.len() - 1 {
+            for (state, backward) in beta[time].iter().enumerate() {
+                let next = self.emission(time, state) + backward - log_likelihood;
+                let tag = self.tag(state);
+                self.for_each_predecessor(state, |p, transition| {
+                    let log_posterior = alpha[time - 1][p] + transition + next;
+                    transitions[self.tag(p) * self.num_tags + tag] += log_posterior.exp();
+                });
+            }
+        }
+
+        (log_likelihood, marginals, transitions)
+    }
+
+    /// Picks the tag with the highest posterior marginal for every column
+    /// but the sentence boundary.
+    pub fn posterior_decode(&self) -> Vec<usize> {
+        self.posteriors()
+            .iter()
+            .map(|marginals| argmax(marginals))
+            .collect()
+    }
+}
+
+fn argmax(scores: &[f64]) -> usize {
+    scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+        .map_or(0, |(i, _)| i)
+}
+
+fn log_sum_exp(scores: &[f64]) -> f64 {
+    let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+
+    max + scores.iter().map(|s| (s - max).exp()).sum::<f64>().ln()
+}
+
+/// Keeps the `width` best states scoring within `threshold` of the best one,
+/// and always the best one, so that the beam never runs empty.
+fn prune(mut states: Vec<(usize, f64)>, width: usize, threshold: Option<f64>) -> Vec<(usize, f64)> {
+    states.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+    let best = states.first().map_or(f64::NEG_INFINITY, |&(_, score)| score);
+    let kept = states
+        .iter()
+        .take(width)
+        .take_while(|&&(_, score)| score > f64::NEG_INFINITY && threshold.is_none_or(|t| score >= best - t))
+        .count();
+
+    states.truncate(kept.max(1));
+    states
+}
+
+fn keep_best(mut candidates: Vec<Backpointer>, k: usize) -> Vec<Backpointer> {
+    candidates.sort_unstable_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(k);
+    candidates
+}
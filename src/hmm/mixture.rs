@@ -0,0 +1,48 @@
+use super::{accuracy, read_tagged_sentences};
+use crate::{POSTaggingHMM, Tagger};
+use crate::tagset::TagMapping;
+use std::path::PathBuf;
+use std::error::Error;
+
+/// Picks the weight of `first` in its mixture with `second` that tags the
+/// sentences of `dev_file`, whose tags are mapped through `mapping`, most
+/// accurately. Tries `steps + 1` evenly spaced weights from 0 to 1,
+/// reporting the accuracy of each one, and keeps the earliest of any tied
+/// weights.
+pub fn tune_mixture_weight(
+    first: &POSTaggingHMM,
+    second: &POSTaggingHMM,
+    dev_file: PathBuf,
+    steps: usize,
+    mapping: &TagMapping,
+    mut on_weight: impl FnMut(f64, f64)
+) -> Result<(f64, POSTaggingHMM), Box<dyn Error>> {
+    if steps == 0 {
+        Err("Expected at least one step between mixture weights")?
+    }
+
+    let (sentences, correct_taggings): (Vec<Vec<String>>, Vec<Vec<String>>) = read_tagged_sentences(dev_file)?
+        .into_iter()
+        .map(|s| mapping.map_sentence(s).into_iter().unzip())
+        .unzip();
+    let mut best: Option<(f64, f64, POSTaggingHMM)> = None;
+
+    for step in 0..=steps {
+        let weight = step as f64 / steps as f64;
+        let model = first.mix(second, weight)?;
+        let predictions = sentences
+            .iter()
+            .map(|s| model.tag(s).0)
+            .collect();
+
+        let accuracy = accuracy(predictions, correct_taggings.clone())?;
+        on_weight(weight, accuracy);
+
+        if best.as_ref().is_none_or(|(_, best_accuracy, _)| accuracy > *best_accuracy) {
+            best = Some((weight, accuracy, model));
+        }
+    }
+
+    let (weight, _, model) = best.unwrap();
+    Ok((weight, model))
+}
@@ -1,10 +1,31 @@
 mod trainer;
 mod model;
+mod interpolation;
+mod lattice;
+mod dense;
+mod em;
+mod self_training;
+mod mixture;
+mod suffix;
+mod affixes;
+mod capitalization;
+mod rules;
+mod tuning;
  
 pub use trainer::*;
 pub use model::*;
-
-use crate::ConditionalStringCounter;
+pub use interpolation::*;
+pub use em::*;
+pub use self_training::*;
+pub use mixture::*;
+pub use suffix::*;
+pub use affixes::*;
+pub use capitalization::*;
+pub use rules::*;
+pub use tuning::*;
+
+use crate::Tagger;
+use crate::tagset::TagMapping;
 use crate::nlp::{extract_word_and_tag, TaggedSentence};
 use indicatif::{ProgressBar, ProgressIterator};
 use std::io::{self, BufReader, BufRead};
@@ -14,37 +35,41 @@ use std::time::Instant;
 use std::fs::File;
 use std::thread;
 
-pub fn evaluate(model: &POSTaggingHMM, data_file: PathBuf) -> Result<(), io::Error> {
-    let f = File::open(data_file)?;
-    let rdr = BufReader::new(f);
+pub fn evaluate<T: Tagger>(model: &T, data_file: PathBuf) -> Result<f64, io::Error> {
+    evaluate_mapped(model, data_file, &TagMapping::default())
+}
 
-    let samples: Vec<String> = rdr.lines().filter_map(Result::ok).collect();
+/// Evaluates a model whose tags are already mapped through `mapping`, such
+/// as a `MappedTagger`, against the mapped tags of `data_file`.
+pub fn evaluate_mapped<T: Tagger>(model: &T, data_file: PathBuf, mapping: &TagMapping) -> Result<f64, io::Error> {
+    let samples = read_lines(data_file)?;
     let (sentences, correct_taggings): (Vec<Vec<String>>, Vec<Vec<String>>) = 
         samples.iter()
-            .map(|s| extract_word_and_tag(s))
+            .map(|s| mapping.map_sentence(extract_word_and_tag(s)))
             .map(Vec::into_iter)
             .map(Iterator::unzip)
             .unzip();
 
     let start = Instant::now();
-    let predictions: Vec<TaggedSentence> = sentences.into_iter()
-        .map(|s| model.predict(s))
+    let predictions: Vec<Vec<String>> = sentences.iter()
+        .map(|s| model.tag(s).0)
         .progress()
         .collect();
     let duration = Instant::now() - start;
     println!("Model evaluation on {} samples took {:.3}s", correct_taggings.len(), duration.as_secs_f64());
 
-    evaluate_accuracies(predictions, correct_taggings);
+    evaluate_accuracies(predictions, correct_taggings)
+}
 
-    Ok(())
+pub fn par_evaluate<T: Tagger + Sync>(model: &T, data_file: PathBuf) -> Result<f64, io::Error> {
+    par_evaluate_mapped(model, data_file, &TagMapping::default())
 }
 
-pub fn par_evaluate(model: &POSTaggingHMM, data_file: PathBuf) -> Result<(), io::Error> {
-    let rdr = BufReader::new(File::open(data_file)?);
+pub fn par_evaluate_mapped<T: Tagger + Sync>(model: &T, data_file: PathBuf, mapping: &TagMapping) -> Result<f64, io::Error> {
     let (sentences, correct_taggings): (Vec<Vec<String>>, Vec<Vec<String>>) = 
-        rdr.lines()
-            .filter_map(Result::ok)
-            .map(|s| extract_word_and_tag(&s))
+        read_lines(data_file)?
+            .into_iter()
+            .map(|s| mapping.map_sentence(extract_word_and_tag(&s)))
             .map(Vec::into_iter)
             .map(Iterator::unzip)
             .unzip();
@@ -71,7 +96,7 @@ pub fn par_evaluate(model: &POSTaggingHMM, data_file: PathBuf) -> Result<(), io:
                 let tx_clone = tx.clone();
                 s.spawn(move || {
                     c.into_iter()
-                        .map(|(i, s)| (i, model.predict(s)))
+                        .map(|(i, s)| (i, model.tag(&s).0))
                         .for_each(|p| {
                             tx_clone.send(p).unwrap();
                         });
@@ -81,10 +106,10 @@ pub fn par_evaluate(model: &POSTaggingHMM, data_file: PathBuf) -> Result<(), io:
 
         let bar = ProgressBar::new(num_predictions as u64);
         predictions = rx.into_iter()
-            .map(|x| { bar.inc(1); x })
+            .inspect(|_| bar.inc(1))
             .collect();
         bar.finish_and_clear();
-        predictions.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        predictions.sort_unstable_by_key(|(i, _)| *i);
     });
 
     let duration = Instant::now() - start;
@@ -94,27 +119,85 @@ pub fn par_evaluate(model: &POSTaggingHMM, data_file: PathBuf) -> Result<(), io:
         .into_iter()
         .map(|(_, s)| s)
         .collect();
-    evaluate_accuracies(predictions, correct_taggings);
+    evaluate_accuracies(predictions, correct_taggings)
+}
+
+/// Reads the lines of `data_file`, skipping lines that are not valid UTF-8
+/// instead of stopping at them.
+fn read_lines(data_file: PathBuf) -> Result<Vec<String>, io::Error> {
+    let rdr = BufReader::new(File::open(data_file)?);
+    let mut lines = Vec::new();
 
-    Ok(())
+    for line in rdr.lines() {
+        match line {
+            Ok(line) => lines.push(line),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => continue,
+            Err(e) => return Err(e)
+        }
+    }
+
+    Ok(lines)
 }
 
-fn evaluate_accuracies(predictions: Vec<TaggedSentence>, correct_tags: Vec<Vec<String>>) {
-    let mut correct_wordtagcounter = ConditionalStringCounter::new();
-    let mut wrong_wordtagcounter = ConditionalStringCounter::new();
+/// Reads whitespace-separated sentences, one per line, skipping blank lines.
+fn read_untagged_sentences(data_file: PathBuf) -> Result<Vec<Vec<String>>, io::Error> {
+    let rdr = BufReader::new(File::open(data_file)?);
+    let mut sentences = Vec::new();
+
+    for line in rdr.lines() {
+        let sentence: Vec<String> = line?
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+
+        if !sentence.is_empty() {
+            sentences.push(sentence);
+        }
+    }
 
+    Ok(sentences)
+}
+
+/// Reads tagged sentences, one per line, skipping blank lines.
+pub fn read_tagged_sentences(data_file: PathBuf) -> Result<Vec<TaggedSentence>, io::Error> {
+    let rdr = BufReader::new(File::open(data_file)?);
+    let mut sentences = Vec::new();
+
+    for line in rdr.lines() {
+        let line = line?;
+        if !line.is_empty() {
+            sentences.push(extract_word_and_tag(&line));
+        }
+    }
+
+    Ok(sentences)
+}
+
+fn evaluate_accuracies(predictions: Vec<Vec<String>>, correct_tags: Vec<Vec<String>>) -> Result<f64, io::Error> {
+    let accuracy = accuracy(predictions, correct_tags)?;
+    println!("Accuracy: {:.03}%", accuracy);
+
+    Ok(accuracy)
+}
+
+/// The percentage of tags predicted correctly, or an error if a tagger did
+/// not predict exactly one tag for every word.
+fn accuracy(predictions: Vec<Vec<String>>, correct_tags: Vec<Vec<String>>) -> Result<f64, io::Error> {
     let mut correct: usize = 0;
     let mut wrong: usize = 0;
 
-    for (predicted, answer) in predictions.into_iter().zip(correct_tags.into_iter()) {
-        assert_eq!(predicted.len(), answer.len());
+    for (predicted, answer) in predictions.into_iter().zip(correct_tags) {
+        if predicted.len() != answer.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Predicted {} tags for a sentence of {} words", predicted.len(), answer.len())
+            ));
+        }
 
-        for ((word, pred_tag), ans_tag) in predicted.into_iter().zip(answer.into_iter()) {
+        for (pred_tag, ans_tag) in predicted.into_iter().zip(answer) {
             if pred_tag == ans_tag {
-                correct_wordtagcounter.increment(&word, &ans_tag);
                 correct += 1;
             } else {
-                wrong_wordtagcounter.increment(&word, &ans_tag);
                 wrong += 1;
             }
         }
@@ -123,6 +206,5 @@ fn evaluate_accuracies(predictions: Vec<TaggedSentence>, correct_tags: Vec<Vec<S
     let correct = correct as f64;
     let wrong = wrong as f64;
 
-    let accuracy = (correct / (correct + wrong)) * 100.0;
-    println!("Accuracy: {:.03}%", accuracy);
+    Ok((correct / (correct + wrong)) * 100.0)
 }
\ No newline at end of file
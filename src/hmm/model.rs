@@ -1,15 +1,134 @@
 use crate::{StringFrequencyDistribution, ConditionalStringFrequencyDistribution};
-use crate::nlp::{get_matching_artificial_tag, TaggedWord, END_TAG};
+use crate::{StringCounter, ConditionalStringCounter, Smoothing, Tagger};
+use super::rules::matching_pseudo_word;
+use super::suffix::is_capitalized;
+use super::{AffixClasses, CapitalizationModel, InterpolatedTransitions, POSTaggingHMMTrainer, SuffixModel, WordClassRules};
+use super::lattice::{Lattice, Transitions};
+use super::dense::DenseModel;
+use crate::nlp::{fold_case, get_capitalization, CaseFolding, Normalizer, TaggedWord, TaggedSentence, END_TAG};
 use std::{
     fs::{File, OpenOptions}, io::{Write, Read}, error::Error,
-    collections::HashMap, path::PathBuf
+    collections::HashMap, path::PathBuf, borrow::Cow
 };
 use serde::{Deserialize, Serialize};
 
-const MODEL_FILE_HEADER: [u8; 4] = *b"VHMM";
+const MODEL_FILE_HEADER: [u8; 4] = *b"VHM2";
+const LEGACY_MODEL_FILE_HEADER: [u8; 4] = *b"VHMM";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HMMOrder {
+    #[default]
+    First,
+    Second
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DecodingStrategy {
+    /// Tag the sentence with the single most likely tag sequence.
+    #[default]
+    Viterbi,
+    /// Tag each token with its most likely tag under the posterior marginals.
+    Posterior,
+    /// Approximate Viterbi that only extends the `width` best states at each
+    /// word, dropping any whose log-likelihood trails the best by more than
+    /// `threshold`.
+    Beam { width: usize, threshold: Option<f64> }
+}
+
+/// Fractional counts gathered by forward-backward over untagged text, with
+/// tags indexed by their position in the model's tag set.
+#[derive(Debug, Default)]
+pub(in crate::hmm) struct ExpectedCounts {
+    pub log_likelihood: f64,
+    initial: Vec<f64>,
+    emissions: HashMap<String, Vec<f64>>,
+    transitions: Vec<f64>
+}
+
+/// The smoothing a model was estimated with, kept so that re-estimating it
+/// smooths the same way.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(in crate::hmm) struct ModelSmoothing {
+    pub initial: Smoothing,
+    pub transition: Smoothing,
+    pub emission: Smoothing,
+    /// The distribution of tags over rare words, which scales the Lidstone α
+    /// of the emissions of every tag.
+    pub emission_scale: Option<StringFrequencyDistribution>
+}
+
+impl ModelSmoothing {
+    /// The initial, emission and transition distributions estimated from
+    /// possibly fractional counts. The initial and transition distributions
+    /// back off to the tag frequencies `tag_weights`, smoothed the way each
+    /// of them is.
+    pub fn estimate(
+        &self,
+        initial_weights: HashMap<String, f64>,
+        emission_weights: HashMap<String, HashMap<String, f64>>,
+        transition_weights: HashMap<String, HashMap<String, f64>>,
+        tag_weights: &HashMap<String, f64>
+    ) -> (StringFrequencyDistribution, ConditionalStringFrequencyDistribution, ConditionalStringFrequencyDistribution) {
+        let tag_distribution =
+            |smoothing| StringFrequencyDistribution::smoothed(tag_weights.clone(), smoothing, 1, None);
+
+        let emission_distribution = match &self.emission_scale {
+            Some(scale) => ConditionalStringFrequencyDistribution::conditionally_smoothed(
+                emission_weights,
+                self.emission,
+                scale
+            ),
+            None => ConditionalStringFrequencyDistribution::smoothed(emission_weights, self.emission, None)
+        };
+
+        let transition_distribution = ConditionalStringFrequencyDistribution::smoothed(
+            transition_weights,
+            self.transition,
+            Some(&tag_distribution(self.transition))
+        );
+
+        let initial_tag_distribution = StringFrequencyDistribution::smoothed(
+            initial_weights,
+            self.initial,
+            1,
+            Some(&tag_distribution(self.initial))
+        );
+
+        (initial_tag_distribution, emission_distribution, transition_distribution)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagConstraint {
+    Fixed(String),
+    OneOf(Vec<String>)
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct POSTaggingHMM {
+    initial_tag_distribution: StringFrequencyDistribution,
+    emission_distribution: ConditionalStringFrequencyDistribution,
+    transition_distribution: ConditionalStringFrequencyDistribution,
+    second_order_transitions: Option<InterpolatedTransitions>,
+    tag_set: Vec<String>,
+    tag_indices: HashMap<String, usize>,
+    tag_dictionary: HashMap<String, Vec<usize>>,
+    suffix_model: Option<SuffixModel>,
+    affix_classes: Option<AffixClasses>,
+    word_class_rules: Option<WordClassRules>,
+    normalizer: Normalizer,
+    capitalization_model: Option<CapitalizationModel>,
+    smoothing: ModelSmoothing,
+    counts: Option<POSTaggingHMMTrainer>,
+    #[serde(skip)]
+    decoding_strategy: DecodingStrategy,
+    #[serde(skip)]
+    dense: DenseModel
+}
+
+/// The model layout written by versions prior to second-order support.
+#[derive(Deserialize)]
+struct LegacyPOSTaggingHMM {
     initial_tag_distribution: StringFrequencyDistribution,
     emission_distribution: ConditionalStringFrequencyDistribution,
     transition_distribution: ConditionalStringFrequencyDistribution,
@@ -17,6 +136,29 @@ pub struct POSTaggingHMM {
     tag_indices: HashMap<String, usize>
 }
 
+impl From<LegacyPOSTaggingHMM> for POSTaggingHMM {
+    fn from(legacy: LegacyPOSTaggingHMM) -> Self {
+        Self {
+            initial_tag_distribution: legacy.initial_tag_distribution,
+            emission_distribution: legacy.emission_distribution,
+            transition_distribution: legacy.transition_distribution,
+            second_order_transitions: None,
+            tag_set: legacy.tag_set,
+            tag_indices: legacy.tag_indices,
+            tag_dictionary: HashMap::new(),
+            suffix_model: None,
+            affix_classes: None,
+            word_class_rules: None,
+            normalizer: Normalizer { form: None, case_folding: CaseFolding::Ascii },
+            capitalization_model: None,
+            smoothing: ModelSmoothing::default(),
+            counts: None,
+            decoding_strategy: DecodingStrategy::default(),
+            dense: DenseModel::default()
+        }
+    }
+}
+
 impl POSTaggingHMM {
     pub(in crate::hmm) fn new(
         initial_tag_distribution: StringFrequencyDistribution,
@@ -36,29 +178,286 @@ impl POSTaggingHMM {
             initial_tag_distribution,
             emission_distribution,
             transition_distribution,
+            second_order_transitions: None,
             tag_set,
-            tag_indices
+            tag_indices,
+            tag_dictionary: HashMap::new(),
+            suffix_model: None,
+            affix_classes: None,
+            word_class_rules: None,
+            normalizer: Normalizer::default(),
+            capitalization_model: None,
+            smoothing: ModelSmoothing::default(),
+            counts: None,
+            decoding_strategy: DecodingStrategy::default(),
+            dense: DenseModel::default()
+        }.compiled()
+    }
+
+    fn compiled(mut self) -> Self {
+        self.dense = DenseModel::compile(
+            &self.tag_set,
+            &self.initial_tag_distribution,
+            &self.emission_distribution,
+            &self.transition_distribution
+        );
+        self
+    }
+
+    pub(in crate::hmm) fn with_second_order(
+        mut self,
+        unigram_counts: &StringCounter,
+        bigram_counts: &ConditionalStringCounter,
+        trigram_counts: &ConditionalStringCounter
+    ) -> Self {
+        self.second_order_transitions = Some(InterpolatedTransitions::estimate(
+            &self.tag_set,
+            unigram_counts,
+            bigram_counts,
+            trigram_counts,
+            &self.transition_distribution
+        ));
+        self
+    }
+
+    pub(in crate::hmm) fn with_tag_dictionary(mut self, dictionary: HashMap<String, Vec<String>>) -> Self {
+        self.tag_dictionary = dictionary
+            .into_iter()
+            .map(|(word, tags)| {
+                let indices: Vec<usize> = tags
+                    .iter()
+                    .filter_map(|tag| self.tag_indices.get(tag).copied())
+                    .collect();
+                (word, indices)
+            })
+            .filter(|(_, indices)| !indices.is_empty())
+            .collect();
+        self
+    }
+
+    /// Scores the words unseen in training with `suffix_model` instead of
+    /// their pseudo-words.
+    pub(in crate::hmm) fn with_suffix_model(mut self, suffix_model: SuffixModel) -> Self {
+        self.suffix_model = Some(suffix_model);
+        self
+    }
+
+    pub fn suffix_model(&self) -> Option<&SuffixModel> {
+        self.suffix_model.as_ref()
+    }
+
+    /// Maps unknown words to the pseudo-words of `affix_classes` instead of
+    /// the hard-coded ones.
+    pub(in crate::hmm) fn with_affix_classes(mut self, affix_classes: AffixClasses) -> Self {
+        self.affix_classes = Some(affix_classes);
+        self
+    }
+
+    pub fn affix_classes(&self) -> Option<&AffixClasses> {
+        self.affix_classes.as_ref()
+    }
+
+    /// Maps unknown words to the classes of `rules` before any affix
+    /// classes.
+    pub(in crate::hmm) fn with_word_class_rules(mut self, rules: WordClassRules) -> Self {
+        self.word_class_rules = Some(rules);
+        self
+    }
+
+    pub fn word_class_rules(&self) -> Option<&WordClassRules> {
+        self.word_class_rules.as_ref()
+    }
+
+    pub(in crate::hmm) fn with_normalizer(mut self, normalizer: Normalizer) -> Self {
+        self.normalizer = normalizer;
+        self
+    }
+
+    /// How words are normalized before they are looked up. Models saved
+    /// before Unicode normalization lowercase ASCII letters only.
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    /// Whether the model tells words apart by case rather than folding it.
+    pub fn is_case_sensitive(&self) -> bool {
+        self.normalizer.case_folding == CaseFolding::None
+    }
+
+    /// Scores every word with how likely each tag is to be capitalized like
+    /// it.
+    pub(in crate::hmm) fn with_capitalization_model(mut self, capitalization_model: CapitalizationModel) -> Self {
+        self.capitalization_model = Some(capitalization_model);
+        self
+    }
+
+    pub fn capitalization_model(&self) -> Option<&CapitalizationModel> {
+        self.capitalization_model.as_ref()
+    }
+
+    /// Keeps the raw counts the model was estimated from so that it can be
+    /// updated with more sentences later.
+    pub(in crate::hmm) fn with_counts(mut self, counts: POSTaggingHMMTrainer) -> Self {
+        self.counts = Some(counts);
+        self
+    }
+
+    pub(in crate::hmm) fn with_smoothing(mut self, smoothing: ModelSmoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn has_counts(&self) -> bool {
+        self.counts.is_some()
+    }
+
+    /// Folds more tagged sentences into the counts the model was estimated
+    /// from and re-estimates it, keeping its decoding strategy.
+    pub fn update(&mut self, tagged_sentences: impl IntoIterator<Item=TaggedSentence>) -> Result<(), Box<dyn Error>> {
+        let counts = match &self.counts {
+            Some(counts) => counts.clone(),
+            None => Err("Model does not keep the counts needed to update it")?
+        };
+
+        let decoding_strategy = self.decoding_strategy;
+        *self = counts.with_kept_counts(true).train_sentences(tagged_sentences).finalize()?;
+        self.decoding_strategy = decoding_strategy;
+        Ok(())
+    }
+
+    /// Builds the model whose initial, emission and transition distributions
+    /// are `weight * self + (1 - weight) * other`. A word keeps a tag
+    /// dictionary entry unless the other model has seen it without one, and
+    /// the suffix and capitalization models of this model are kept over
+    /// those of the other. Unknown words are mapped to pseudo-words by this
+    /// model's word class rules and affix classes. The mixture keeps no
+    /// counts, since no counts estimate it, so update the models before
+    /// mixing them rather than after. Fails for second-order models, whose
+    /// interpolated transitions cannot be mixed.
+    pub fn mix(&self, other: &Self, weight: f64) -> Result<Self, Box<dyn Error>> {
+        if self.order() != HMMOrder::First || other.order() != HMMOrder::First {
+            Err("Mixing only supports first-order models")?
+        }
+
+        if self.normalizer != other.normalizer {
+            Err("Cannot mix models that normalize words differently")?
+        }
+
+        if !(0.0..=1.0).contains(&weight) {
+            Err("The mixture weight must be between 0 and 1")?
         }
+
+        let mut tag_dictionary = self.tag_dictionary_entries();
+        tag_dictionary.retain(|word, _| {
+            !other.dense.contains_word(word) || other.tag_dictionary.contains_key(word)
+        });
+        for (word, tags) in other.tag_dictionary_entries() {
+            if self.dense.contains_word(&word) && !self.tag_dictionary.contains_key(&word) {
+                continue;
+            }
+
+            let entry = tag_dictionary.entry(word).or_default();
+            for tag in tags {
+                if !entry.contains(&tag) {
+                    entry.push(tag);
+                }
+            }
+        }
+
+        let model = Self::new(
+            self.initial_tag_distribution.mix(&other.initial_tag_distribution, weight),
+            self.emission_distribution.mix_with(
+                &other.emission_distribution,
+                weight,
+                |d, word| self.unseen_emission(d, word),
+                |d, word| other.unseen_emission(d, word)
+            ),
+            self.transition_distribution.mix(&other.transition_distribution, weight)
+        ).with_tag_dictionary(tag_dictionary);
+
+        Ok(Self {
+            suffix_model: self.suffix_model.clone().or_else(|| other.suffix_model.clone()),
+            affix_classes: self.affix_classes.clone(),
+            word_class_rules: self.word_class_rules.clone(),
+            normalizer: self.normalizer,
+            capitalization_model: self.capitalization_model.clone().or_else(|| other.capitalization_model.clone()),
+            smoothing: self.smoothing.clone(),
+            ..model
+        })
+    }
+
+    /// The likelihood under `emissions` of a word the other model of a
+    /// mixture has seen, which is that of its pseudo-word if this model has
+    /// never seen it.
+    fn unseen_emission(&self, emissions: &StringFrequencyDistribution, word: &str) -> f64 {
+        if self.dense.contains_word(word) {
+            emissions.smoothed_default()
+        } else {
+            emissions.get_likelihood(self.emission_word(word))
+        }
+    }
+
+    pub fn tag_dictionary_len(&self) -> usize {
+        self.tag_dictionary.len()
+    }
+
+    fn tag_dictionary_entries(&self) -> HashMap<String, Vec<String>> {
+        self.tag_dictionary
+            .iter()
+            .map(|(word, tags)| (word.clone(), tags.iter().map(|&t| self.tag_set[t].clone()).collect()))
+            .collect()
+    }
+
+    pub fn order(&self) -> HMMOrder {
+        match self.second_order_transitions {
+            Some(_) => HMMOrder::Second,
+            None => HMMOrder::First
+        }
+    }
+
+    pub fn interpolation_weights(&self) -> Option<[f64; 3]> {
+        self.second_order_transitions
+            .as_ref()
+            .map(InterpolatedTransitions::weights)
+    }
+
+    pub fn decoding_strategy(&self) -> DecodingStrategy {
+        self.decoding_strategy
+    }
+
+    pub fn set_decoding_strategy(&mut self, strategy: DecodingStrategy) {
+        self.decoding_strategy = strategy;
+    }
+
+    pub fn tag_set(&self) -> &[String] {
+        &self.tag_set
     }
 
     pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
         let mut file = File::open(path)?;
         let mut header: [u8; 4] = [0; 4];
         file.read_exact(&mut header)?;
-        if header != MODEL_FILE_HEADER {
-            Err("Unknown file structure")?
-        }
 
         let mut model_bytes = Vec::new();
         file.read_to_end(&mut model_bytes)?;
 
-        Ok(bincode::deserialize(&model_bytes)?)
+        let model: Self = match header {
+            MODEL_FILE_HEADER => bincode::deserialize(&model_bytes)?,
+            LEGACY_MODEL_FILE_HEADER => {
+                let legacy: LegacyPOSTaggingHMM = bincode::deserialize(&model_bytes)?;
+                legacy.into()
+            },
+            _ => Err("Unknown file structure")?
+        };
+
+        Ok(model.compiled())
     }
 
     pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(path)?;
         
         file.write_all(&MODEL_FILE_HEADER)?;
@@ -67,85 +466,315 @@ impl POSTaggingHMM {
         Ok(())
     }
 
-    pub fn predict(&self, mut sentence: Vec<String>) -> Vec<TaggedWord> {
-        sentence = sentence
-            .iter()
-            .map(|s| s.to_ascii_lowercase())
-            .collect();
-        sentence.push(END_TAG.into());
+    pub fn predict(&self, sentence: Vec<String>) -> Vec<TaggedWord> {
+        let sentence = Self::prepare_sentence(sentence);
+        let tags = self.decode(&self.lattice(&sentence));
+
+        self.tag_sentence(sentence, tags)
+    }
+
+    /// Predicts the best tagging that agrees with the caller's constraints,
+    /// given as one optional constraint per word of the sentence.
+    pub fn predict_constrained(
+        &self,
+        sentence: Vec<String>,
+        constraints: &[Option<TagConstraint>]
+    ) -> Result<Vec<TaggedWord>, Box<dyn Error>> {
+        if constraints.len() != sentence.len() {
+            Err("Expected exactly one constraint per word")?
+        }
+
+        let mut allowed_tags = vec![None; constraints.len()];
+        for (time, constraint) in constraints.iter().enumerate() {
+            let tags: Vec<&String> = match constraint {
+                Some(TagConstraint::Fixed(tag)) => vec![tag],
+                Some(TagConstraint::OneOf(tags)) => tags.iter().collect(),
+                None => continue
+            };
+
+            let mut allowed = vec![false; self.tag_set.len()];
+            for tag in tags {
+                match self.tag_indices.get(tag) {
+                    Some(&i) => allowed[i] = true,
+                    None => Err(format!("Unknown tag {tag}"))?
+                }
+            }
+
+            if !allowed.contains(&true) {
+                Err(format!("No tags allowed for word {}", time + 1))?
+            }
 
-        let mut b = vec![vec![""; sentence.len()]; self.tag_set.len()];
+            allowed_tags[time] = Some(allowed);
+        }
+
+        let sentence = Self::prepare_sentence(sentence);
+        let tags = self.decode(&self.constrained_lattice(&sentence, &allowed_tags));
+        Ok(self.tag_sentence(sentence, tags))
+    }
+
+    fn decode(&self, lattice: &Lattice) -> Vec<usize> {
+        match self.decoding_strategy {
+            DecodingStrategy::Viterbi => lattice.viterbi().1,
+            DecodingStrategy::Posterior => lattice.posterior_decode(),
+            DecodingStrategy::Beam { width, threshold } => lattice.beam(width, threshold)
+        }
+    }
+
+    /// Predicts the Viterbi tagging along with its log-likelihood,
+    /// regardless of the model's decoding strategy.
+    pub fn predict_with_score(&self, sentence: Vec<String>) -> (f64, TaggedSentence) {
+        let sentence = Self::prepare_sentence(sentence);
+        let (score, tags) = self.lattice(&sentence).viterbi();
+
+        (score, self.tag_sentence(sentence, tags))
+    }
+
+    pub fn predict_n_best(&self, sentence: Vec<String>, k: usize) -> Vec<(f64, TaggedSentence)> {
+        let sentence = Self::prepare_sentence(sentence);
+
+        self.lattice(&sentence)
+            .n_best(k)
+            .into_iter()
+            .map(|(score, tags)| (score, self.tag_sentence(sentence.clone(), tags)))
+            .collect()
+    }
 
-        let mut cv: Vec<f64> = vec![0.0; self.tag_set.len()];
-        let mut pv: Vec<f64> = self.tag_set
+    pub fn posteriors(&self, sentence: Vec<String>) -> Vec<(String, Vec<f64>)> {
+        let mut sentence = Self::prepare_sentence(sentence);
+        let posteriors = self.lattice(&sentence).posteriors();
+        sentence.pop();
+
+        sentence.into_iter().zip(posteriors).collect()
+    }
+
+    fn prepare_sentence(mut sentence: Vec<String>) -> Vec<String> {
+        sentence.push(END_TAG.into());
+        sentence
+    }
+
+    /// The words of a prepared sentence as the model knows them, normalized
+    /// as in training. A case-sensitive model folds the case of an unknown
+    /// first word if that makes it known.
+    fn vocabulary_words<'a>(&self, sentence: &'a [String]) -> Vec<Cow<'a, str>> {
+        let (end, words) = sentence.split_last().unwrap();
+        words
             .iter()
-            .map(|tag| {
-                self.initial_tag_distribution.get_likelihood(tag) 
-                    + self.emission_distribution.get_likelihood(tag, &sentence[0]).unwrap()
+            .enumerate()
+            .map(|(position, word)| {
+                let word = self.normalizer.normalize(word);
+                if position == 0 && self.is_case_sensitive() && !self.dense.contains_word(&word) {
+                    let folded = fold_case(&word);
+                    if self.dense.contains_word(&folded) {
+                        return Cow::Owned(folded);
+                    }
+                }
+
+                word
             })
+            .chain(std::iter::once(Cow::Borrowed(end.as_str())))
+            .collect()
+    }
+
+    /// Adds the expected counts of tagging `sentence` under this model.
+    pub(in crate::hmm) fn accumulate_expectations(&self, sentence: Vec<String>, counts: &mut ExpectedCounts) {
+        let sentence = Self::prepare_sentence(sentence);
+        let (log_likelihood, marginals, transitions) = self.lattice(&sentence).expectations();
+        if !log_likelihood.is_finite() {
+            return;
+        }
+
+        let num_tags = self.tag_set.len();
+        counts.log_likelihood += log_likelihood;
+        if let Some(first) = marginals.first() {
+            counts.initial.resize(num_tags, 0.0);
+            counts.initial.iter_mut().zip(first).for_each(|(c, p)| *c += p);
+        }
+
+        for (time, (word, marginals)) in self.vocabulary_words(&sentence).iter().zip(marginals).enumerate() {
+            let row = counts.emissions
+                .entry(self.scored_word(time, word).to_string())
+                .or_insert_with(|| vec![0.0; num_tags]);
+            row.iter_mut().zip(marginals).for_each(|(c, p)| *c += p);
+        }
+
+        counts.transitions.resize(num_tags * num_tags, 0.0);
+        counts.transitions.iter_mut().zip(transitions).for_each(|(c, p)| *c += p);
+    }
+
+    /// Builds a new model from expected counts gathered with this model,
+    /// smoothed the way this model is.
+    pub(in crate::hmm) fn reestimate(&self, counts: ExpectedCounts) -> Self {
+        let num_tags = self.tag_set.len();
+        let initial_weights = self.tag_set.iter().cloned()
+            .zip(counts.initial.into_iter().chain(std::iter::repeat(0.0)))
             .collect();
 
-        for (time, word) in sentence.iter().enumerate().skip(1) {
-            let is_unseen = !self.emission_distribution.inner_key_exists(word);
-            let mut emission_word = word.as_str();
-            if is_unseen {
-                if let Some(artificial_tag) = get_matching_artificial_tag(word) {
-                    emission_word = artificial_tag;
-                } 
+        let transitions = counts.transitions;
+        let transition_weights = self.tag_set.iter().enumerate().map(|(p, prev_tag)| {
+            let weights = self.tag_set
+                .iter()
+                .enumerate()
+                .map(|(c, curr_tag)| (curr_tag.clone(), transitions.get(p * num_tags + c).copied().unwrap_or(0.0)))
+                .collect();
+            (prev_tag.clone(), weights)
+        }).collect();
+
+        let mut emission_weights: HashMap<String, HashMap<String, f64>> = self.tag_set.iter()
+            .map(|tag| (tag.clone(), HashMap::new()))
+            .collect();
+        let mut tag_weights: HashMap<String, f64> = HashMap::new();
+        for (word, row) in counts.emissions {
+            for (tag, weight) in self.tag_set.iter().zip(row) {
+                if weight > 0.0 {
+                    emission_weights.get_mut(tag).unwrap().insert(word.clone(), weight);
+                    *tag_weights.entry(tag.clone()).or_default() += weight;
+                }
             }
+        }
 
-            for (cti, curr_tag) in self.tag_set.iter().enumerate() {
-                let emission = self.emission_distribution
-                    .get_likelihood(curr_tag, emission_word)
-                    .unwrap();
+        let (initial_tag_distribution, emission_distribution, transition_distribution) =
+            self.smoothing.estimate(initial_weights, emission_weights, transition_weights, &tag_weights);
 
-                let (best_score, best_tag): (f64, &str) = self.tag_set
-                    .iter()
-                    .enumerate()
-                    .map(|(pti, prev_tag)| {
-                        let transition = self.transition_distribution
-                            .get_likelihood(prev_tag, &curr_tag)
-                            .unwrap();
-                        (pv[pti] + emission + transition, prev_tag.as_str())
-                    })
-                    .max_by(|(s1, _), (s2, _)| s1.total_cmp(s2))
-                    .unwrap();
+        Self {
+            suffix_model: self.suffix_model.clone(),
+            affix_classes: self.affix_classes.clone(),
+            word_class_rules: self.word_class_rules.clone(),
+            normalizer: self.normalizer,
+            capitalization_model: self.capitalization_model.clone(),
+            smoothing: self.smoothing.clone(),
+            ..Self::new(initial_tag_distribution, emission_distribution, transition_distribution)
+                .with_tag_dictionary(self.tag_dictionary_entries())
+        }
+    }
 
-                b[cti][time] = best_tag;
-                cv[cti] = best_score;
-            }
+    fn tag_sentence(&self, mut sentence: Vec<String>, tags: Vec<usize>) -> TaggedSentence {
+        sentence.pop();
+        sentence
+            .into_iter()
+            .zip(tags)
+            .map(|(word, tag)| (word, self.tag_set[tag].clone()))
+            .collect()
+    }
+
+    fn emission_word<'a>(&'a self, word: &'a str) -> &'a str {
+        if !self.dense.contains_word(word) {
+            let artificial_tag = matching_pseudo_word(
+                word,
+                self.word_class_rules.as_ref(),
+                self.affix_classes.as_ref()
+            );
 
-            pv = cv;
-            cv = vec![0.0; self.tag_set.len()];
+            if let Some(artificial_tag) = artificial_tag {
+                return artificial_tag;
+            }
         }
 
-        let predicted_tags = self.backtrack_trellis(b, pv, sentence.len());
+        word
+    }
+
+    /// The word whose emissions score `word` at position `time`. The first
+    /// word is scored as written, never as the pseudo-word of its class.
+    fn scored_word<'a>(&'a self, time: usize, word: &'a str) -> &'a str {
+        match time {
+            0 => word,
+            _ => self.emission_word(word)
+        }
+    }
 
-        sentence.into_iter().zip(predicted_tags).collect()
+    fn lattice(&self, sentence: &[String]) -> Lattice<'_> {
+        self.constrained_lattice(sentence, &[])
     }
 
-    fn backtrack_trellis(&self, potential_tags: Vec<Vec<&str>>, final_likelihoods: Vec<f64>, sentence_len: usize) -> Vec<String> {
-        let (_, best_tag): (f64, &str) = final_likelihoods.into_iter()
-            .zip(potential_tags.iter())
-            .map(|(vi, bi)| (vi, bi[sentence_len - 1]))
-            .max_by(|(s1, _), (s2, _)| s1.total_cmp(s2))
-            .unwrap();
+    /// The lattice of a prepared sentence where only the tags marked in
+    /// `allowed_tags` are possible at each constrained word. A constraint
+    /// replaces the tag dictionary entry of its word rather than narrowing
+    /// it, so that callers may pin a word to a tag never seen with it.
+    fn constrained_lattice(&self, sentence: &[String], allowed_tags: &[Option<Vec<bool>>]) -> Lattice<'_> {
+        let transitions = match &self.second_order_transitions {
+            Some(transitions) => Transitions::SecondOrder(transitions),
+            None => Transitions::FirstOrder(self.dense.transitions())
+        };
 
-        let mut previous_tag = best_tag;
-        let mut prev_tag_idx = self.tag_indices.get(previous_tag);
-        let mut predicted_tags = vec![END_TAG.to_string(); sentence_len - 1];
-        
-        for (time, pred_tag) in predicted_tags.iter_mut().enumerate().rev() {
-            *pred_tag = previous_tag.to_string();
+        let words = self.vocabulary_words(sentence);
+        let end = sentence.len() - 1;
+        let emissions = words
+            .iter()
+            .enumerate()
+            .map(|(time, word)| {
+                let is_known = time == end || self.dense.contains_word(word);
+                let emissions = match &self.suffix_model {
+                    Some(suffixes) if !is_known => {
+                        let pseudo_word = self.dense.emission_row(self.emission_word(word));
+                        let is_capitalized = is_capitalized(&sentence[time]);
+                        Cow::Owned(suffixes.log_likelihoods(word, is_capitalized, &self.tag_set, pseudo_word))
+                    },
+                    _ => Cow::Borrowed(self.dense.emission_row(self.scored_word(time, word)))
+                };
+
+                // The emissions of the words a case-sensitive model knows
+                // already tell their capitalizations apart.
+                match &self.capitalization_model {
+                    Some(capitalizations) if time != end && (!is_known || !self.is_case_sensitive()) => {
+                        let capitalization = get_capitalization(&sentence[time], time == 0);
+                        let scores = capitalizations.log_likelihoods(capitalization, is_known, &self.tag_set);
+                        Cow::Owned(emissions.iter().zip(scores).map(|(e, c)| e + c).collect())
+                    },
+                    _ => emissions
+                }
+            })
+            .collect();
 
-            if prev_tag_idx.is_none() {
-                break;
+        let mut lattice = Lattice::new(self.dense.initial(), transitions, emissions);
+        for (time, word) in words.iter().enumerate() {
+            match allowed_tags.get(time).and_then(Option::as_ref) {
+                Some(allowed) => lattice.restrict(time, |tag| allowed[tag]),
+                None => if let Some(tags) = self.tag_dictionary.get(word.as_ref()) {
+                    lattice.restrict(time, |tag| tags.contains(&tag));
+                }
             }
+        }
+
+        lattice
+    }
+}
 
-            previous_tag = potential_tags[*prev_tag_idx.unwrap()][time];
-            prev_tag_idx = self.tag_indices.get(previous_tag);
+impl Tagger for POSTaggingHMM {
+    /// Posterior decoding scores every tag with its posterior probability.
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        let sentence = Self::prepare_sentence(sentence.to_vec());
+        let lattice = self.lattice(&sentence);
+
+        if self.decoding_strategy != DecodingStrategy::Posterior {
+            let tags = self.decode(&lattice)
+                .into_iter()
+                .take(sentence.len() - 1)
+                .map(|tag| self.tag_set[tag].clone())
+                .collect();
+            return (tags, None);
         }
 
-        predicted_tags
+        let (tags, scores) = lattice
+            .posteriors()
+            .into_iter()
+            .map(|marginals| {
+                let (tag, &score) = marginals
+                    .iter()
+                    .enumerate()
+                    .max_by(|(_, p1), (_, p2)| p1.total_cmp(p2))
+                    .unwrap();
+                (self.tag_set[tag].clone(), score)
+            })
+            .unzip();
+
+        (tags, Some(scores))
     }
-}
\ No newline at end of file
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        let posteriors = self.posteriors(sentence.to_vec())
+            .into_iter()
+            .map(|(_, marginals)| self.tag_set.iter().cloned().zip(marginals).collect())
+            .collect();
+
+        Some(posteriors)
+    }
+}
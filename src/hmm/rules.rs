@@ -0,0 +1,152 @@
+use crate::nlp::{get_matching_artificial_tag, is_mostly_numeric, is_punctuation};
+use super::AffixClasses;
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::io::{BufReader, BufRead};
+use std::path::PathBuf;
+use std::error::Error;
+use std::fs::File;
+
+#[derive(Clone, Debug)]
+enum Rule {
+    Regex(Regex),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    /// Words made up mostly of numbers.
+    Number,
+    /// Words made up only of punctuation.
+    Punctuation
+}
+
+impl Rule {
+    fn parse(rule: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(pattern) = rule.strip_prefix('/').and_then(|r| r.strip_suffix('/')) {
+            return Ok(Rule::Regex(Regex::new(pattern)?));
+        }
+
+        Ok(match rule.split_once(':') {
+            Some(("prefix", prefix)) => Rule::Prefix(prefix.to_string()),
+            Some(("suffix", suffix)) => Rule::Suffix(suffix.to_string()),
+            Some(("contains", text)) => Rule::Contains(text.to_string()),
+            None if rule == "number" => Rule::Number,
+            None if rule == "punctuation" => Rule::Punctuation,
+            _ => Err(format!("Unknown rule {rule}"))?
+        })
+    }
+
+    fn matches(&self, word: &str) -> bool {
+        match self {
+            Rule::Regex(regex) => regex.is_match(word),
+            Rule::Prefix(prefix) => word.starts_with(prefix.as_str()),
+            Rule::Suffix(suffix) => word.ends_with(suffix.as_str()),
+            Rule::Contains(text) => word.contains(text.as_str()),
+            Rule::Number => is_mostly_numeric(word),
+            Rule::Punctuation => !word.is_empty() && word.chars().all(is_punctuation)
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+            Rule::Prefix(prefix) => write!(f, "prefix:{prefix}"),
+            Rule::Suffix(suffix) => write!(f, "suffix:{suffix}"),
+            Rule::Contains(text) => write!(f, "contains:{text}"),
+            Rule::Number => write!(f, "number"),
+            Rule::Punctuation => write!(f, "punctuation")
+        }
+    }
+}
+
+/// Pseudo-word classes of unknown words read from a rules file. The rules
+/// are saved as written and compiled again when a model is loaded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "Vec<(String, String)>", try_from = "Vec<(String, String)>")]
+pub struct WordClassRules {
+    rules: Vec<(String, Rule)>
+}
+
+impl TryFrom<Vec<(String, String)>> for WordClassRules {
+    type Error = String;
+
+    fn try_from(rules: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let rules = rules
+            .into_iter()
+            .map(|(class, rule)| Ok((class, Rule::parse(&rule).map_err(|e| e.to_string())?)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { rules })
+    }
+}
+
+impl From<WordClassRules> for Vec<(String, String)> {
+    fn from(rules: WordClassRules) -> Self {
+        rules.rules
+            .into_iter()
+            .map(|(class, rule)| (class, rule.to_string()))
+            .collect()
+    }
+}
+
+impl WordClassRules {
+    /// Reads a rules file with one `CLASS RULE` pair per line, where a rule
+    /// is a `/regex/`, `prefix:TEXT`, `suffix:TEXT`, `contains:TEXT`,
+    /// `number` or `punctuation`. Blank lines and lines starting with `#`
+    /// are skipped.
+    pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let rdr = BufReader::new(File::open(path)?);
+        let mut rules = Vec::new();
+
+        for (number, line) in rdr.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((class, rule)) = line.split_once(char::is_whitespace) else {
+                Err(format!("Expected a CLASS RULE pair on line {}", number + 1))?
+            };
+
+            let rule = Rule::parse(rule.trim())
+                .map_err(|e| format!("Invalid rule on line {}: {}", number + 1, e))?;
+            rules.push((class.to_string(), rule));
+        }
+
+        Ok(Self { rules })
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The class of the first rule that `word` matches.
+    pub fn matching_class(&self, word: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(_, rule)| rule.matches(word))
+            .map(|(class, _)| class.as_str())
+    }
+}
+
+/// The pseudo-word of an unknown word: the class of the first matching rule,
+/// then the learned affix classes, and the hard-coded classes only when
+/// neither rules nor learned affixes are given.
+pub(in crate::hmm) fn matching_pseudo_word<'a>(
+    word: &str,
+    rules: Option<&'a WordClassRules>,
+    affix_classes: Option<&'a AffixClasses>
+) -> Option<&'a str> {
+    let class = rules.and_then(|rules| rules.matching_class(word));
+    match (rules, affix_classes) {
+        (_, Some(classes)) => class.or_else(|| classes.matching_artificial_tag(word)),
+        (Some(_), None) => class,
+        (None, None) => get_matching_artificial_tag(word)
+    }
+}
@@ -0,0 +1,70 @@
+use super::read_untagged_sentences;
+use crate::{POSTaggingHMM, POSTaggingHMMTrainer};
+use crate::nlp::TaggedSentence;
+use std::path::PathBuf;
+use std::error::Error;
+
+/// Adapts a model to untagged text by repeatedly tagging it and retraining
+/// on the tagged data together with the sentences the model was most
+/// confident about.
+pub struct SelfTrainer {
+    trainer: POSTaggingHMMTrainer,
+    model: Option<POSTaggingHMM>,
+    sentences: Vec<Vec<String>>
+}
+
+impl SelfTrainer {
+    pub fn new(trainer: POSTaggingHMMTrainer) -> Self {
+        Self { trainer, model: None, sentences: Vec::new() }
+    }
+
+    /// Tags the first round with `model` instead of one finalized from the
+    /// trainer.
+    pub fn with_model(mut self, model: POSTaggingHMM) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn train(mut self, data_file: PathBuf) -> Result<Self, Box<dyn Error>> {
+        self.sentences.extend(read_untagged_sentences(data_file)?);
+        Ok(self)
+    }
+
+    /// Keeps the taggings whose Viterbi log-likelihood per word is at least
+    /// `threshold`.
+    fn confident_taggings(&self, model: &POSTaggingHMM, threshold: f64) -> Vec<TaggedSentence> {
+        self.sentences
+            .iter()
+            .filter_map(|sentence| {
+                let (score, tagging) = model.predict_with_score(sentence.clone());
+                (score / sentence.len() as f64 >= threshold).then_some(tagging)
+            })
+            .collect()
+    }
+
+    /// Runs `rounds` rounds of self-training, reporting the number of
+    /// sentences kept out of the total after every round.
+    pub fn run(
+        mut self,
+        rounds: usize,
+        threshold: f64,
+        mut on_round: impl FnMut(usize, usize, usize)
+    ) -> Result<POSTaggingHMM, Box<dyn Error>> {
+        let mut model = match self.model.take() {
+            Some(model) => model,
+            None => self.trainer.clone().finalize()?
+        };
+
+        for round in 1..=rounds {
+            let taggings = self.confident_taggings(&model, threshold);
+            on_round(round, taggings.len(), self.sentences.len());
+
+            model = self.trainer
+                .clone()
+                .train_sentences(taggings)
+                .finalize()?;
+        }
+
+        Ok(model)
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{StringCounter, ConditionalStringCounter};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The final letters of `word`, shortest first, up to `max_length` letters.
+fn suffixes(word: &str, max_length: usize) -> impl Iterator<Item=&str> {
+    word.char_indices()
+        .rev()
+        .take(max_length)
+        .map(move |(i, _)| &word[i..])
+}
+
+/// The tag counts of the rare words ending in every suffix, for either the
+/// lowercase or the capitalized words.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SuffixTrie {
+    priors: Vec<f64>,
+    suffix_counts: HashMap<String, Vec<f64>>
+}
+
+impl SuffixTrie {
+    fn new(num_tags: usize) -> Self {
+        Self { priors: vec![0.0; num_tags], suffix_counts: HashMap::new() }
+    }
+
+    fn add(&mut self, word: &str, tag: usize, count: f64, max_length: usize) {
+        if count == 0.0 {
+            return;
+        }
+
+        let num_tags = self.priors.len();
+        self.priors[tag] += count;
+        for suffix in suffixes(word, max_length) {
+            self.suffix_counts
+                .entry(suffix.to_string())
+                .or_insert_with(|| vec![0.0; num_tags])[tag] += count;
+        }
+    }
+
+    /// Turns the tag counts into probabilities, adding one word spread over
+    /// the tags by `priors` so that tags only seen in the other trie are
+    /// unlikely rather than impossible.
+    fn normalize(&mut self, priors: &[f64]) {
+        let total: f64 = self.priors.iter().sum::<f64>() + 1.0;
+        for (p, prior) in self.priors.iter_mut().zip(priors) {
+            *p = (*p + prior) / total;
+        }
+    }
+}
+
+/// Estimates the tags of unknown words from their final letters using the
+/// successive abstraction of Brants (2000), "TnT -- A Statistical
+/// Part-of-Speech Tagger", over the words seen in training at most as many
+/// times as the rare word threshold. Like TnT, capitalized and lowercase
+/// words are counted in separate tries.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SuffixModel {
+    max_length: usize,
+    theta: f64,
+    tag_indices: HashMap<String, usize>,
+    lowercase: SuffixTrie,
+    capitalized: SuffixTrie
+}
+
+impl SuffixModel {
+    /// Counts the rare words of `emission_counts` in the trie of their case,
+    /// where `capitalized_counts` holds how often each was capitalized.
+    pub(in crate::hmm) fn estimate(
+        emission_counts: &ConditionalStringCounter,
+        capitalized_counts: &ConditionalStringCounter,
+        max_length: usize,
+        rare_word_threshold: usize
+    ) -> Self {
+        let mut word_counts = StringCounter::new();
+        for counts in emission_counts.values() {
+            word_counts.add_all(counts.clone());
+        }
+
+        let tag_indices: HashMap<String, usize> = emission_counts
+            .keys()
+            .cloned()
+            .enumerate()
+            .map(|(i, tag)| (tag, i))
+            .collect();
+        let num_tags = tag_indices.len();
+
+        let mut lowercase = SuffixTrie::new(num_tags);
+        let mut capitalized = SuffixTrie::new(num_tags);
+        for (tag, counts) in emission_counts.iter() {
+            let t = tag_indices[tag];
+            let capitalized_counts = capitalized_counts.get(tag);
+            for (word, &count) in counts.iter() {
+                if word_counts.get(word) > rare_word_threshold {
+                    continue;
+                }
+
+                let upper = capitalized_counts.map_or(0, |c| c.get(word));
+                lowercase.add(word, t, (count - upper) as f64, max_length);
+                capitalized.add(word, t, upper as f64, max_length);
+            }
+        }
+
+        let mut priors: Vec<f64> = lowercase.priors.iter().zip(&capitalized.priors).map(|(l, c)| l + c).collect();
+        let total: f64 = priors.iter().sum();
+        if total > 0.0 {
+            priors.iter_mut().for_each(|p| *p /= total);
+        }
+
+        // TnT weighs every suffix length by the standard deviation of the
+        // unconditional tag probabilities of the rare words.
+        let theta = if num_tags > 1 {
+            let mean = 1.0 / num_tags as f64;
+            let variance = priors.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / (num_tags - 1) as f64;
+            variance.sqrt()
+        } else {
+            0.0
+        };
+
+        lowercase.normalize(&priors);
+        capitalized.normalize(&priors);
+
+        Self { max_length, theta, tag_indices, lowercase, capitalized }
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// The log-likelihood of `word` under every tag of `tag_set`, combining
+    /// the `pseudo_word` log-likelihoods of its class with the evidence of
+    /// its suffixes, P(tag | suffix) / P(tag), from the trie of its case.
+    /// Tags never seen with a rare word of either case keep the likelihood
+    /// of the pseudo-word alone.
+    pub fn log_likelihoods(&self, word: &str, is_capitalized: bool, tag_set: &[String], pseudo_word: &[f64]) -> Vec<f64> {
+        let trie = match is_capitalized {
+            true => &self.capitalized,
+            false => &self.lowercase
+        };
+
+        let mut probabilities = trie.priors.clone();
+        for suffix in suffixes(word, self.max_length) {
+            let Some(counts) = trie.suffix_counts.get(suffix) else { break };
+            let total: f64 = counts.iter().sum();
+
+            for (p, count) in probabilities.iter_mut().zip(counts) {
+                *p = (count / total + self.theta * *p) / (1.0 + self.theta);
+            }
+        }
+
+        tag_set
+            .iter()
+            .zip(pseudo_word)
+            .map(|(tag, &pseudo_word)| match self.tag_indices.get(tag) {
+                Some(&t) if trie.priors[t] > 0.0 => {
+                    pseudo_word + probabilities[t].ln() - trie.priors[t].ln()
+                },
+                _ => pseudo_word
+            })
+            .collect()
+    }
+}
+
+/// Whether `token` starts with a capital letter, which picks the trie it is
+/// counted in and looked up in. Sentence-initial words are no exception, as
+/// in TnT.
+pub(in crate::hmm) fn is_capitalized(token: &str) -> bool {
+    token.chars().find(|c| c.is_alphabetic()).is_some_and(char::is_uppercase)
+}
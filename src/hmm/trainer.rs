@@ -1,57 +1,310 @@
-use crate::nlp::{get_matching_artificial_tag, extract_word_and_tag};
-use crate::POSTaggingHMM;
+use crate::nlp::{get_capitalization, CaseFolding, NormalizationForm, Normalizer, TaggedSentence, START_TAG};
+use super::interpolation::tag_pair_key;
+use super::rules::matching_pseudo_word;
+use super::suffix::is_capitalized;
+use super::{read_tagged_sentences, AffixClasses, CapitalizationModel, ModelSmoothing, SuffixModel, WordClassRules};
+use crate::{POSTaggingHMM, HMMOrder};
 use crate::utils::*;
 
 use serde::{Deserialize, Serialize};
-use std::io::{BufReader, BufRead};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
 use std::path::PathBuf;
 use std::error::Error;
-use std::fs::File;
 
-#[derive(Debug, Serialize, Deserialize)]
+const CHECKPOINT_FILE_HEADER: [u8; 4] = *b"VHCK";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct POSTaggingHMMTrainer {
     was_trained: bool,
+    order: HMMOrder,
+    tag_dictionary_cutoff: Option<usize>,
+    suffix_length: Option<usize>,
+    learned_affixes: Option<usize>,
+    word_class_rules: Option<WordClassRules>,
+    normalizer: Normalizer,
+    capitalization_features: bool,
+    rare_word_threshold: usize,
+    initial_smoothing: Smoothing,
+    transition_smoothing: Smoothing,
+    emission_smoothing: Smoothing,
+    /// Whether finalized models keep these counts. Chosen for each run rather
+    /// than saved with checkpoints.
+    #[serde(skip)]
+    keep_counts: bool,
     initial_tag_counts: StringCounter,
     tag_emission_counts: ConditionalStringCounter,
-    tag_transition_counts: ConditionalStringCounter
+    tag_transition_counts: ConditionalStringCounter,
+    tag_trigram_counts: ConditionalStringCounter,
+    tag_capitalization_counts: ConditionalStringCounter,
+    word_capitalization_counts: ConditionalStringCounter,
+    tag_capitalized_word_counts: ConditionalStringCounter
+}
+
+impl Default for POSTaggingHMMTrainer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl POSTaggingHMMTrainer {
     pub fn new() -> Self {
         Self {
             was_trained: false,
+            order: HMMOrder::First,
+            tag_dictionary_cutoff: None,
+            suffix_length: None,
+            learned_affixes: None,
+            word_class_rules: None,
+            normalizer: Normalizer::default(),
+            capitalization_features: false,
+            rare_word_threshold: 1,
+            initial_smoothing: Smoothing::default(),
+            transition_smoothing: Smoothing::default(),
+            emission_smoothing: Smoothing::default(),
+            keep_counts: false,
             initial_tag_counts: StringCounter::new(),
             tag_emission_counts: ConditionalStringCounter::new(),
-            tag_transition_counts: ConditionalStringCounter::new()
+            tag_transition_counts: ConditionalStringCounter::new(),
+            tag_trigram_counts: ConditionalStringCounter::new(),
+            tag_capitalization_counts: ConditionalStringCounter::new(),
+            word_capitalization_counts: ConditionalStringCounter::new(),
+            tag_capitalized_word_counts: ConditionalStringCounter::new()
+        }
+    }
+
+    /// Sets the order of the model. Only second-order trainers count tag
+    /// trigrams, so the order has to be set before training.
+    pub fn with_order(mut self, order: HMMOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Restricts words seen at least `cutoff` times to the tags they were
+    /// observed with during training.
+    pub fn with_tag_dictionary_cutoff(mut self, cutoff: usize) -> Self {
+        self.tag_dictionary_cutoff = Some(cutoff);
+        self
+    }
+
+    /// Weighs the pseudo-word scores of unknown words with a suffix model
+    /// over their last `length` letters, trained on the words seen at most
+    /// as many times as the rare word threshold.
+    pub fn with_suffix_length(mut self, length: usize) -> Self {
+        self.suffix_length = Some(length);
+        self
+    }
+
+    /// Replaces the hard-coded affix classes of unknown words with up to
+    /// `count` prefixes and suffixes learned from the hapax words.
+    pub fn with_learned_affixes(mut self, count: usize) -> Self {
+        self.learned_affixes = Some(count);
+        self
+    }
+
+    /// Maps unknown words to the classes of `rules` before any affix
+    /// classes, replacing the hard-coded classes.
+    pub fn with_word_class_rules(mut self, rules: WordClassRules) -> Self {
+        self.word_class_rules = Some(rules);
+        self
+    }
+
+    /// Keeps the case of words instead of folding it, so that "US" and "us"
+    /// are different words to the model.
+    pub fn with_case_sensitivity(mut self, case_sensitive: bool) -> Self {
+        self.normalizer.case_folding = match case_sensitive {
+            true => CaseFolding::None,
+            false => CaseFolding::Full
+        };
+        self
+    }
+
+    /// Brings words into the Unicode normalization `form` before folding
+    /// their case, both in training and when tagging.
+    pub fn with_normalization_form(mut self, form: NormalizationForm) -> Self {
+        self.normalizer.form = Some(form);
+        self
+    }
+
+    /// Scores every word with how likely its tag is to be capitalized the
+    /// way the word is, whether or not words are lowercased.
+    pub fn with_capitalization_features(mut self, capitalization_features: bool) -> Self {
+        self.capitalization_features = capitalization_features;
+        self
+    }
+
+    /// Lets words seen at most `threshold` times with a tag, rather than only
+    /// the hapax words, stand in for unknown words with that tag.
+    pub fn with_rare_word_threshold(mut self, threshold: usize) -> Self {
+        self.rare_word_threshold = threshold;
+        self
+    }
+
+    /// Smooths the initial, transition and emission distributions with
+    /// `smoothing`. Strategies other than Lidstone smoothing spread the
+    /// probability they set aside in the initial and transition
+    /// distributions over the unigram distribution of tags.
+    pub fn with_smoothing(self, smoothing: Smoothing) -> Self {
+        self.with_initial_smoothing(smoothing)
+            .with_transition_smoothing(smoothing)
+            .with_emission_smoothing(smoothing)
+    }
+
+    pub fn with_initial_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.initial_smoothing = smoothing;
+        self
+    }
+
+    pub fn with_transition_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.transition_smoothing = smoothing;
+        self
+    }
+
+    /// Lidstone smoothing of the emissions scales α for every tag by how
+    /// likely the tag is for a hapax word.
+    pub fn with_emission_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.emission_smoothing = smoothing;
+        self
+    }
+
+    /// Keeps the counts in finalized models so that `POSTaggingHMM::update`
+    /// can fold more sentences into them, at the cost of larger files.
+    pub fn with_kept_counts(mut self, keep_counts: bool) -> Self {
+        self.keep_counts = keep_counts;
+        self
+    }
+
+    /// Adds the counts of `other` to this trainer, keeping this trainer's
+    /// settings. Fails if the trainers counted words differently.
+    pub fn merge(mut self, other: POSTaggingHMMTrainer) -> Result<Self, Box<dyn Error>> {
+        if self.order != other.order {
+            Err("Cannot merge the counts of first-order and second-order trainers")?
+        }
+        if self.normalizer != other.normalizer {
+            Err("Cannot merge the counts of trainers that normalize words differently")?
+        }
+        if self.capitalization_features != other.capitalization_features {
+            Err("Cannot merge the counts of trainers that disagree on capitalization features")?
+        }
+
+        self.was_trained |= other.was_trained;
+        self.initial_tag_counts.add_all(other.initial_tag_counts);
+        self.tag_emission_counts.add_all(other.tag_emission_counts);
+        self.tag_transition_counts.add_all(other.tag_transition_counts);
+        self.tag_trigram_counts.add_all(other.tag_trigram_counts);
+        self.tag_capitalization_counts.add_all(other.tag_capitalization_counts);
+        self.word_capitalization_counts.add_all(other.word_capitalization_counts);
+        self.tag_capitalized_word_counts.add_all(other.tag_capitalized_word_counts);
+        Ok(self)
+    }
+
+    pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(path)?;
+        let mut header: [u8; 4] = [0; 4];
+        file.read_exact(&mut header)?;
+
+        if header != CHECKPOINT_FILE_HEADER {
+            Err("Unknown file structure")?
         }
+
+        let mut checkpoint_bytes = Vec::new();
+        file.read_to_end(&mut checkpoint_bytes)?;
+
+        Ok(bincode::deserialize(&checkpoint_bytes)?)
     }
 
-    pub fn train(mut self, data_file: PathBuf) -> Result<Self, Box<dyn Error>> {
-        let f = File::open(data_file)?;
-        let rdr = BufReader::new(f);
+    /// Saves the raw counts so that training can be resumed or merged with
+    /// counts from other corpora before finalizing.
+    pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        file.write_all(&CHECKPOINT_FILE_HEADER)?;
+        file.write_all(bincode::serialize(&self)?.as_ref())?;
 
+        Ok(())
+    }
+
+    fn build_tag_dictionary(&self, cutoff: usize) -> HashMap<String, Vec<String>> {
+        let mut word_counts = StringCounter::new();
+        let mut dictionary: HashMap<String, Vec<String>> = HashMap::new();
+        for (tag, counts) in self.tag_emission_counts.iter() {
+            for (word, &count) in counts.iter() {
+                word_counts.increment_by(word, count);
+                dictionary.entry(word.clone()).or_default().push(tag.clone());
+            }
+        }
+
+        dictionary.retain(|word, _| word_counts.get(word) >= cutoff);
+        dictionary
+    }
+
+    /// The capitalization `word` was most often seen with, if capitalization
+    /// features are being counted.
+    fn most_common_capitalization(&self, word: &str) -> Option<&str> {
+        self.word_capitalization_counts
+            .get(word)?
+            .iter()
+            .max_by(|(c1, n1), (c2, n2)| n1.cmp(n2).then_with(|| c2.cmp(c1)))
+            .map(|(capitalization, _)| capitalization.as_str())
+    }
+
+    /// Counts the tagged sentences of `data_file`, one per line, skipping
+    /// blank lines.
+    pub fn train(self, data_file: PathBuf) -> Result<Self, Box<dyn Error>> {
+        Ok(self.train_sentences(read_tagged_sentences(data_file)?))
+    }
+
+    /// Counts sentences that were tagged elsewhere, such as by another model
+    /// or by hand. Their words are normalized like those read by `train`, and
+    /// empty sentences are skipped.
+    pub fn train_sentences(mut self, sentences: impl IntoIterator<Item=TaggedSentence>) -> Self {
         self.was_trained = true;
+        for sentence in sentences.into_iter().filter(|s| !s.is_empty()) {
+            self.count_sentence(sentence);
+        }
 
-        for line in rdr.lines() {
-            let sentence = line?;
-            let tagged_line = extract_word_and_tag(&sentence);
+        self
+    }
 
-            let mut iter = tagged_line.into_iter();
-            let (w0, t0) = iter.next().unwrap();
-            
-            self.initial_tag_counts.increment(&t0);
-            self.tag_emission_counts.increment(&t0, &w0);
-
-            let mut previous_tag = t0;
-            for (word, tag) in iter {
-                self.tag_emission_counts.increment(&tag, &word);
-                self.tag_transition_counts.increment(&previous_tag, &tag);
-                
-                previous_tag = tag;
+    fn count_sentence(&mut self, mut tagged_sentence: TaggedSentence) {
+        for (position, (word, tag)) in tagged_sentence.iter_mut().enumerate() {
+            let capitalization = get_capitalization(word, position == 0).as_str();
+            // The suffix model counts capitalized words apart from the others.
+            let capitalized = is_capitalized(word);
+            *word = self.normalizer.normalize(word).into_owned();
+
+            if capitalized {
+                self.tag_capitalized_word_counts.increment(tag, word);
+            }
+
+            if self.capitalization_features {
+                self.tag_capitalization_counts.increment(tag, capitalization);
+                self.word_capitalization_counts.increment(word, capitalization);
             }
         }
 
-        Ok(self)
+        let mut iter = tagged_sentence.into_iter();
+        let (w0, t0) = iter.next().unwrap();
+        
+        self.initial_tag_counts.increment(&t0);
+        self.tag_emission_counts.increment(&t0, &w0);
+
+        let mut second_previous_tag = START_TAG.to_string();
+        let mut previous_tag = t0;
+        for (word, tag) in iter {
+            self.tag_emission_counts.increment(&tag, &word);
+            self.tag_transition_counts.increment(&previous_tag, &tag);
+            if self.order == HMMOrder::Second {
+                self.tag_trigram_counts.increment(&tag_pair_key(&second_previous_tag, &previous_tag), &tag);
+            }
+            
+            second_previous_tag = std::mem::replace(&mut previous_tag, tag);
+        }
     }
 
     pub fn finalize(mut self) -> Result<POSTaggingHMM, Box<dyn Error>> {
@@ -59,45 +312,111 @@ impl POSTaggingHMMTrainer {
             Err("Model has not yet been trained")?
         }
 
+        let counts = self.keep_counts.then(|| self.clone());
+
+        let mut tag_counts = StringCounter::new();
+        for (tag, word_counts) in self.tag_emission_counts.iter() {
+            tag_counts.increment_by(tag, word_counts.total());
+        }
+
+        let tag_dictionary = self.tag_dictionary_cutoff
+            .map(|cutoff| self.build_tag_dictionary(cutoff))
+            .unwrap_or_default();
+
+        let suffix_model = self.suffix_length
+            .map(|length| SuffixModel::estimate(
+                &self.tag_emission_counts,
+                &self.tag_capitalized_word_counts,
+                length,
+                self.rare_word_threshold
+            ));
+        let affix_classes = self.learned_affixes
+            .map(|count| AffixClasses::learn(&self.tag_emission_counts, count));
+
         let mut hapax_counts = StringCounter::new();
+        let mut hapax_capitalization_counts = ConditionalStringCounter::new();
         let mut artificial_word_counts = ConditionalStringCounter::new();
         
         for (tag, word_counts) in self.tag_emission_counts.iter() {
             for (word, count) in word_counts.iter() {
-                if count == &1 {
+                if *count <= self.rare_word_threshold {
                     hapax_counts.increment(tag);
 
-                    if let Some(aw) = get_matching_artificial_tag(word) {
+                    let artificial_word = matching_pseudo_word(
+                        word,
+                        self.word_class_rules.as_ref(),
+                        affix_classes.as_ref()
+                    );
+
+                    if let Some(aw) = artificial_word {
                         artificial_word_counts.increment(tag, aw);
                     }
+
+                    if let Some(capitalization) = self.most_common_capitalization(word) {
+                        hapax_capitalization_counts.increment(tag, capitalization);
+                    }
                 }
             }
         }
 
+        let capitalization_model = self.capitalization_features.then(|| CapitalizationModel::estimate(
+            self.tag_capitalization_counts.clone(),
+            hapax_capitalization_counts
+        ));
+
         self.tag_emission_counts.extend(artificial_word_counts);
         let hapax_distribution = 
             StringFrequencyDistribution::with_default_smoothing(hapax_counts);
 
-        let emission_distribution = 
-            ConditionalStringFrequencyDistribution::with_conditional_smoothing(
-                self.tag_emission_counts, 
-                hapax_distribution
-            );
-
-        let transition_distribution = 
-            ConditionalStringFrequencyDistribution::with_default_smoothing(
-                self.tag_transition_counts
-            );
+        let smoothing = ModelSmoothing {
+            initial: self.initial_smoothing,
+            transition: self.transition_smoothing,
+            emission: self.emission_smoothing,
+            emission_scale: Some(hapax_distribution)
+        };
+        let (initial_tag_distribution, emission_distribution, transition_distribution) = smoothing.estimate(
+            self.initial_tag_counts.into_weights(),
+            self.tag_emission_counts.into_weights(),
+            self.tag_transition_counts.clone().into_weights(),
+            &tag_counts.clone().into_weights()
+        );
 
-        let initial_tag_distribution =
-            StringFrequencyDistribution::with_default_smoothing(
-                self.initial_tag_counts
-            );
-
-        Ok(POSTaggingHMM::new(
+        let model = POSTaggingHMM::new(
             initial_tag_distribution,
             emission_distribution,
             transition_distribution
-        ))
+        )
+        .with_tag_dictionary(tag_dictionary)
+        .with_normalizer(self.normalizer)
+        .with_smoothing(smoothing);
+        let model = match counts {
+            Some(counts) => model.with_counts(counts),
+            None => model
+        };
+        let model = match suffix_model {
+            Some(suffix_model) => model.with_suffix_model(suffix_model),
+            None => model
+        };
+        let model = match affix_classes {
+            Some(affix_classes) => model.with_affix_classes(affix_classes),
+            None => model
+        };
+        let model = match self.word_class_rules {
+            Some(rules) => model.with_word_class_rules(rules),
+            None => model
+        };
+        let model = match capitalization_model {
+            Some(capitalization_model) => model.with_capitalization_model(capitalization_model),
+            None => model
+        };
+
+        Ok(match self.order {
+            HMMOrder::First => model,
+            HMMOrder::Second => model.with_second_order(
+                &tag_counts,
+                &self.tag_transition_counts,
+                &self.tag_trigram_counts
+            )
+        })
     }
 }
\ No newline at end of file
@@ -0,0 +1,140 @@
+use super::{accuracy, read_tagged_sentences};
+use crate::{POSTaggingHMM, POSTaggingHMMTrainer, Smoothing, Tagger};
+use crate::tagset::TagMapping;
+use std::path::PathBuf;
+use std::error::Error;
+
+/// The settings read when a trainer is finalized, which can be tuned without
+/// counting the training data again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hyperparameters {
+    pub smoothing: Smoothing,
+    pub rare_word_threshold: usize
+}
+
+impl Hyperparameters {
+    /// Finalizes with the smoothing of the candidate on every distribution,
+    /// replacing any smoothing the trainer had for one of them.
+    fn apply(&self, trainer: &POSTaggingHMMTrainer) -> POSTaggingHMMTrainer {
+        trainer
+            .clone()
+            .with_smoothing(self.smoothing)
+            .with_rare_word_threshold(self.rare_word_threshold)
+    }
+}
+
+/// Candidates along with their accuracies, best first.
+pub type Ranking = Vec<(Hyperparameters, f64)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Search {
+    /// Every combination of the given smoothings and thresholds.
+    Grid,
+    /// `trials` draws of a smoothing among those given, with its constant
+    /// drawn between the smallest and largest given for that smoothing, and
+    /// of a threshold between the smallest and largest given.
+    Random { trials: usize, seed: u64 }
+}
+
+/// The hyperparameters `search` picks from the given smoothings and rare
+/// word thresholds.
+pub fn search_candidates(smoothings: &[Smoothing], thresholds: &[usize], search: Search) -> Vec<Hyperparameters> {
+    match search {
+        Search::Grid => smoothings
+            .iter()
+            .flat_map(|&smoothing| thresholds.iter().map(move |&rare_word_threshold| Hyperparameters {
+                smoothing,
+                rare_word_threshold
+            }))
+            .collect(),
+        Search::Random { .. } if smoothings.is_empty() || thresholds.is_empty() => Vec::new(),
+        Search::Random { trials, seed } => {
+            let mut rng = fastrand::Rng::with_seed(seed);
+            let constants = |pick: fn(&Smoothing) -> Option<f64>| {
+                let constants = smoothings.iter().filter_map(pick);
+                let min = constants.clone().fold(f64::INFINITY, f64::min);
+                (min, constants.fold(min, f64::max))
+            };
+            let (min_alpha, max_alpha) = constants(|s| match s {
+                Smoothing::Lidstone(alpha) => Some(*alpha),
+                _ => None
+            });
+            let (min_discount, max_discount) = constants(|s| match s {
+                Smoothing::AbsoluteDiscounting(discount) => Some(*discount),
+                _ => None
+            });
+            let min_threshold = *thresholds.iter().min().unwrap();
+            let max_threshold = *thresholds.iter().max().unwrap();
+
+            (0..trials)
+                .map(|_| {
+                    // Lidstone α spans orders of magnitude, so draw its log.
+                    let smoothing = match smoothings[rng.usize(..smoothings.len())] {
+                        Smoothing::Lidstone(_) => Smoothing::Lidstone(round(
+                            (min_alpha.ln() + rng.f64() * (max_alpha.ln() - min_alpha.ln())).exp()
+                        )),
+                        Smoothing::AbsoluteDiscounting(_) => Smoothing::AbsoluteDiscounting(round(
+                            min_discount + rng.f64() * (max_discount - min_discount)
+                        )),
+                        smoothing => smoothing
+                    };
+
+                    Hyperparameters {
+                        smoothing,
+                        rare_word_threshold: rng.usize(min_threshold..=max_threshold)
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Rounds a drawn constant to three significant digits so that it prints
+/// short enough to pass back to `train`.
+fn round(constant: f64) -> f64 {
+    format!("{constant:.2e}").parse().unwrap_or(constant)
+}
+
+/// Finalizes `trainer` with each of `candidates` and ranks them by how
+/// accurately the resulting models tag the sentences of `dev_file`, whose
+/// tags are mapped through `mapping`, best first and keeping the earlier of
+/// any tied candidates. Reports the accuracy of each candidate as it is
+/// tried, and returns the ranking along with the model of the best candidate.
+pub fn tune_hyperparameters(
+    trainer: &POSTaggingHMMTrainer,
+    candidates: Vec<Hyperparameters>,
+    dev_file: PathBuf,
+    mapping: &TagMapping,
+    mut on_candidate: impl FnMut(&Hyperparameters, f64)
+) -> Result<(Ranking, POSTaggingHMM), Box<dyn Error>> {
+    if candidates.is_empty() {
+        Err("Expected at least one candidate to tune")?
+    }
+
+    let (sentences, correct_taggings): (Vec<Vec<String>>, Vec<Vec<String>>) = read_tagged_sentences(dev_file)?
+        .into_iter()
+        .map(|s| mapping.map_sentence(s).into_iter().unzip())
+        .unzip();
+    let mut ranking = Vec::with_capacity(candidates.len());
+    let mut best: Option<(f64, POSTaggingHMM)> = None;
+
+    for candidate in candidates {
+        let model = candidate.apply(trainer).finalize()?;
+        let predictions = sentences
+            .iter()
+            .map(|s| model.tag(s).0)
+            .collect();
+
+        let accuracy = accuracy(predictions, correct_taggings.clone())?;
+        on_candidate(&candidate, accuracy);
+        ranking.push((candidate, accuracy));
+
+        if best.as_ref().is_none_or(|(best_accuracy, _)| accuracy > *best_accuracy) {
+            best = Some((accuracy, model));
+        }
+    }
+
+    ranking.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let (_, model) = best.unwrap();
+    Ok((ranking, model))
+}
@@ -1,6 +1,13 @@
 pub mod utils;
+pub mod tagger;
 pub mod hmm;
+pub mod perceptron;
+pub mod crf;
+pub mod ensemble;
+pub mod tagset;
 pub mod nlp;
+mod linear;
 
 pub use utils::*;
-pub use hmm::*;
\ No newline at end of file
+pub use tagger::*;
+pub use hmm::*;
@@ -0,0 +1,287 @@
+//! Helpers shared by the taggers that score words with sparse features.
+
+use crate::nlp::{extract_word_and_tag, CaseFolding, NormalizationForm, Normalizer, TaggedSentence};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{File, OpenOptions}, io::{BufRead, BufReader, Read, Write}, error::Error,
+    collections::{BTreeSet, HashMap}, path::PathBuf
+};
+
+const BEFORE_SENTENCE: &str = "<s>";
+const AFTER_SENTENCE: &str = "</s>";
+
+pub(crate) fn suffix(word: &str, length: usize) -> &str {
+    word.char_indices()
+        .rev()
+        .nth(length - 1)
+        .map_or(word, |(i, _)| &word[i..])
+}
+
+pub(crate) fn prefix(word: &str, length: usize) -> &str {
+    word.char_indices()
+        .nth(length)
+        .map_or(word, |(i, _)| &word[..i])
+}
+
+/// The word `offset` positions away from `index`, or a boundary marker when
+/// that falls outside of the sentence.
+pub(crate) fn neighbour(sentence: &[String], index: usize, offset: isize) -> &str {
+    match index.checked_add_signed(offset) {
+        Some(i) if i < sentence.len() => sentence[i].as_str(),
+        Some(_) => AFTER_SENTENCE,
+        None => BEFORE_SENTENCE
+    }
+}
+
+/// The words of `sentence` normalized by `normalizer`, which the word
+/// features read, along with its tokens in the same normalization form but
+/// with their case kept, which the spelling features read.
+pub(crate) fn words_and_tokens(normalizer: &Normalizer, sentence: &[String]) -> (Vec<String>, Vec<String>) {
+    let spelling = Normalizer { case_folding: CaseFolding::None, ..*normalizer };
+    let tokens: Vec<String> = sentence
+        .iter()
+        .map(|token| spelling.normalize(token).into_owned())
+        .collect();
+    let words = tokens
+        .iter()
+        .map(|token| normalizer.normalize(token).into_owned())
+        .collect();
+
+    (words, tokens)
+}
+
+/// How a family of linear taggers learns its weights, along with the
+/// features of each token that it learns them for.
+pub trait LearningRule {
+    /// The features of the token at `index`. Word features read the
+    /// normalized `words`, while spelling features read the `tokens` with
+    /// their case kept.
+    fn token_features(&self, words: &[String], tokens: &[String], index: usize) -> Vec<String>;
+}
+
+/// A training sentence as the indices of the features of every word and of
+/// its tags.
+pub(crate) struct Example {
+    pub features: Vec<Vec<usize>>,
+    pub tags: Vec<usize>
+}
+
+/// The training sentences of a linear tagger, indexed for learning.
+pub(crate) struct TrainingSet {
+    pub tag_set: Vec<String>,
+    pub feature_indices: HashMap<String, usize>,
+    pub examples: Vec<Example>,
+    pub normalizer: Normalizer
+}
+
+/// Collects the tagged sentences a linear tagger is trained on, leaving how
+/// its weights are learned to the learning rule `R`.
+#[derive(Debug)]
+pub struct LinearTrainer<R> {
+    rule: R,
+    normalizer: Normalizer,
+    sentences: Vec<TaggedSentence>
+}
+
+impl<R: LearningRule + Default> Default for LinearTrainer<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: LearningRule + Default> LinearTrainer<R> {
+    pub fn new() -> Self {
+        Self { rule: R::default(), normalizer: Normalizer::default(), sentences: Vec::new() }
+    }
+}
+
+impl<R: LearningRule> LinearTrainer<R> {
+    pub(crate) fn rule_mut(&mut self) -> &mut R {
+        &mut self.rule
+    }
+
+    /// Keeps the case of words in the word features instead of folding it.
+    pub fn with_case_sensitivity(mut self, case_sensitive: bool) -> Self {
+        self.normalizer.case_folding = match case_sensitive {
+            true => CaseFolding::None,
+            false => CaseFolding::Full
+        };
+        self
+    }
+
+    /// Brings words into the Unicode normalization `form` before extracting
+    /// their features, both in training and when tagging.
+    pub fn with_normalization_form(mut self, form: NormalizationForm) -> Self {
+        self.normalizer.form = Some(form);
+        self
+    }
+
+    pub fn train(self, data_file: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let rdr = BufReader::new(File::open(data_file)?);
+
+        let mut sentences = Vec::new();
+        for line in rdr.lines() {
+            let sentence = line?;
+            if !sentence.is_empty() {
+                sentences.push(extract_word_and_tag(&sentence));
+            }
+        }
+
+        Ok(self.train_sentences(sentences))
+    }
+
+    /// Adds sentences that were read or tagged elsewhere.
+    pub fn train_sentences(mut self, sentences: impl IntoIterator<Item=TaggedSentence>) -> Self {
+        self.sentences.extend(sentences.into_iter().filter(|s| !s.is_empty()));
+        self
+    }
+
+    /// Indexes the tags and the features of the training sentences, handing
+    /// them back along with the learning rule.
+    pub(crate) fn into_training_set(self) -> Result<(R, TrainingSet), Box<dyn Error>> {
+        if self.sentences.is_empty() {
+            Err("Model has not yet been trained")?
+        }
+
+        let tag_set: Vec<String> = self.sentences
+            .iter()
+            .flatten()
+            .map(|(_, tag)| tag.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let tag_indices: HashMap<&str, usize> = tag_set
+            .iter()
+            .enumerate()
+            .map(|(i, tag)| (tag.as_str(), i))
+            .collect();
+
+        let mut feature_indices: HashMap<String, usize> = HashMap::new();
+        let examples: Vec<Example> = self.sentences
+            .iter()
+            .map(|tagged| {
+                let sentence: Vec<String> = tagged.iter().map(|(w, _)| w.clone()).collect();
+                let (words, tokens) = words_and_tokens(&self.normalizer, &sentence);
+                let features = (0..words.len())
+                    .map(|i| {
+                        self.rule
+                            .token_features(&words, &tokens, i)
+                            .into_iter()
+                            .map(|f| {
+                                let next = feature_indices.len();
+                                *feature_indices.entry(f).or_insert(next)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                let tags = tagged.iter().map(|(_, t)| tag_indices[t.as_str()]).collect();
+
+                Example { features, tags }
+            })
+            .collect();
+
+        let training_set = TrainingSet { tag_set, feature_indices, examples, normalizer: self.normalizer };
+        Ok((self.rule, training_set))
+    }
+}
+
+/// Reads a model saved by `save` with the same `header`.
+pub(crate) fn from_file<M: DeserializeOwned>(header: [u8; 4], path: PathBuf) -> Result<M, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut file_header: [u8; 4] = [0; 4];
+    file.read_exact(&mut file_header)?;
+
+    if file_header != header {
+        Err("Unknown file structure")?
+    }
+
+    let mut model_bytes = Vec::new();
+    file.read_to_end(&mut model_bytes)?;
+
+    Ok(bincode::deserialize(&model_bytes)?)
+}
+
+/// Saves `model` to `path` after the `header` that identifies its kind.
+pub(crate) fn save<M: Serialize>(model: &M, header: [u8; 4], path: PathBuf) -> Result<(), Box<dyn Error>> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    file.write_all(&header)?;
+    file.write_all(bincode::serialize(model)?.as_ref())?;
+
+    Ok(())
+}
+
+/// Sums the weights of every word's features for each tag, laid out as
+/// [word][tag].
+pub(crate) fn emission_scores(weights: &[f64], num_tags: usize, features: &[Vec<usize>]) -> Vec<f64> {
+    let mut scores = vec![0.0; features.len() * num_tags];
+    for (row, word_features) in scores.chunks_mut(num_tags).zip(features) {
+        for &f in word_features {
+            for (score, weight) in row.iter_mut().zip(&weights[f * num_tags..(f + 1) * num_tags]) {
+                *score += weight;
+            }
+        }
+    }
+
+    scores
+}
+
+/// Finds the highest-scoring tag sequence given the feature indices of every
+/// word. Feature weights are laid out as [feature][tag] and transition
+/// weights as [previous tag][tag], with the sentence start as the last
+/// previous tag.
+pub(crate) fn viterbi(
+    weights: &[f64],
+    transitions: &[f64],
+    num_tags: usize,
+    features: &[Vec<usize>]
+) -> Vec<usize> {
+    if features.is_empty() {
+        return Vec::new();
+    }
+
+    let emissions = emission_scores(weights, num_tags, features);
+    let emission = |time: usize, tag: usize| emissions[time * num_tags + tag];
+
+    let start = num_tags * num_tags;
+    let mut scores: Vec<f64> = (0..num_tags)
+        .map(|tag| transitions[start + tag] + emission(0, tag))
+        .collect();
+
+    let mut backpointers = Vec::with_capacity(features.len() - 1);
+    for time in 1..features.len() {
+        let (next, bt): (Vec<f64>, Vec<usize>) = (0..num_tags)
+            .map(|tag| {
+                let (previous, score) = scores
+                    .iter()
+                    .enumerate()
+                    .map(|(previous, score)| (previous, score + transitions[previous * num_tags + tag]))
+                    .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+                    .unwrap();
+                (score + emission(time, tag), previous)
+            })
+            .unzip();
+
+        scores = next;
+        backpointers.push(bt);
+    }
+
+    let (mut tag, _) = scores
+        .iter()
+        .enumerate()
+        .max_by(|(_, s1), (_, s2)| s1.total_cmp(s2))
+        .unwrap();
+
+    let mut tags = vec![tag];
+    for bt in backpointers.iter().rev() {
+        tag = bt[tag];
+        tags.push(tag);
+    }
+
+    tags.reverse();
+    tags
+}
@@ -1,6 +1,8 @@
-use std::{path::PathBuf, time::Instant, error::Error, fs::File, io::{self, BufReader, Write}};
-use clap::{Args, Parser, Subcommand};
-use pos_tagger::hmm;
+use std::{path::PathBuf, time::Instant, error::Error, fs::File, io::{self, BufReader, Read, Write}};
+use std::collections::HashMap;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use pos_tagger::{hmm, perceptron, crf, ensemble, tagset, Smoothing, Tagger};
+use pos_tagger::nlp::{NormalizationForm, TaggedSentence};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -15,6 +17,23 @@ enum Command {
     /// Trains a model, saves it to a file for future use, and optionally 
     /// evaluates the model on some data.
     Train(TrainArgs),
+    /// Re-estimates a model on untagged sentences with Baum-Welch (EM) and 
+    /// saves it to a file.
+    TrainEm(TrainEmArgs),
+    /// Adapts a model to untagged sentences by retraining on its most 
+    /// confident taggings of them and saves it to a file.
+    SelfTrain(SelfTrainArgs),
+    /// Trains an HMM once and saves the one whose smoothing and rare word 
+    /// threshold tag a dev file most accurately.
+    Tune(TuneArgs),
+    /// Sums the counts of several training checkpoints into one.
+    MergeCounts(MergeCountsArgs),
+    /// Adds more tagged sentences to an HMM's training counts, re-estimates 
+    /// it, and saves it to a file.
+    Update(UpdateArgs),
+    /// Builds an HMM whose distributions are a weighted mixture of those of 
+    /// two HMMs and saves it to a file.
+    Mix(MixArgs),
     /// Evaluate a pre-trained model on some data.
     Evaluate(EvaluateArgs),
     /// Predict the POS tagging of some sentnces using a pre-trained model 
@@ -25,38 +44,636 @@ enum Command {
 #[derive(Args)]
 struct TrainArgs {
     /// Paths to all of the data files used to train the model.
-    #[arg(short, required=true)]
+    #[arg(short, required_unless_present="resume")]
     data_files: Vec<PathBuf>,
     /// The path to save the trained model to.
     #[arg(short, required=true)]
     out_file: PathBuf,
     /// The path to a data file to evaluate the model.
     #[arg(short)]
-    eval_file: Option<PathBuf>
+    eval_file: Option<PathBuf>,
+    /// The kind of tagger to train.
+    #[arg(long, value_enum, default_value_t=TaggerKind::Hmm)]
+    tagger: TaggerKind,
+    /// The number of passes the perceptron or CRF makes over the training 
+    /// data. Defaults to 5 for the perceptron and 10 for the CRF.
+    #[arg(long)]
+    epochs: Option<usize>,
+    /// Save the raw HMM training counts to FILE so that training can be 
+    /// resumed or merged later.
+    #[arg(long, value_name="FILE")]
+    checkpoint: Option<PathBuf>,
+    /// Start HMM training from the counts saved in FILE.
+    #[arg(long, value_name="FILE")]
+    resume: Vec<PathBuf>,
+    #[command(flatten)]
+    model: ModelArgs,
+    #[command(flatten)]
+    crf: CrfArgs,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
 }
 
 #[derive(Args)]
-struct EvaluateArgs {
-    /// The path to the saved pre-trained model.
+struct TuneArgs {
+    /// Paths to all of the data files used to train the model.
+    #[arg(short, required=true)]
+    data_files: Vec<PathBuf>,
+    /// The path to save the best model to.
+    #[arg(short, required=true)]
+    out_file: PathBuf,
+    /// The path to a data file to evaluate the best model.
+    #[arg(short)]
+    eval_file: Option<PathBuf>,
+    /// The data file whose sentences the candidates are ranked on.
+    #[arg(long, value_name="FILE", required=true)]
+    dev_file: PathBuf,
+    /// Comma-separated smoothings to try on every distribution, replacing 
+    /// the smoothing of the model.
+    #[arg(long, value_name="SPEC", value_delimiter=',', 
+          default_value="lidstone:0.00001,lidstone:0.01,witten-bell,good-turing,absolute:0.75")]
+    smoothings: Vec<Smoothing>,
+    /// Comma-separated rare word thresholds to try.
+    #[arg(long, value_name="N", value_delimiter=',', default_value="1,2,3")]
+    rare_word_thresholds: Vec<usize>,
+    /// How to pick the candidates from the smoothings and thresholds.
+    #[arg(long, value_enum, default_value_t=SearchKind::Grid)]
+    search: SearchKind,
+    /// The number of candidates a random search tries.
+    #[arg(long, value_name="N", default_value_t=20)]
+    trials: usize,
+    /// The seed of a random search.
+    #[arg(long, value_name="N", default_value_t=0)]
+    seed: u64,
+    #[command(flatten)]
+    model: ModelArgs,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SearchKind {
+    /// Try every combination of the smoothings and thresholds.
+    Grid,
+    /// Try random smoothings among those given, with constants drawn 
+    /// between the smallest and largest given, and thresholds likewise.
+    Random
+}
+
+#[derive(Args)]
+struct MergeCountsArgs {
+    /// Paths to the training checkpoints to sum.
+    #[arg(short, required=true)]
+    checkpoint_files: Vec<PathBuf>,
+    /// The path to save the merged checkpoint to.
+    #[arg(short, required=true)]
+    out_file: PathBuf
+}
+
+#[derive(Args)]
+struct UpdateArgs {
+    /// The path to the HMM to update.
     #[arg(short, required=true)]
     model_file: PathBuf,
+    /// Paths to the tagged data files to add to the model's counts.
+    #[arg(short, required=true)]
+    data_files: Vec<PathBuf>,
+    /// The path to save the updated model to.
+    #[arg(short, required=true)]
+    out_file: PathBuf,
+    /// The path to a data file to evaluate the model.
+    #[arg(short)]
+    eval_file: Option<PathBuf>,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+#[derive(Args)]
+struct MixArgs {
+    /// Paths to the two first-order HMMs to mix.
+    #[arg(short, required=true, num_args=2)]
+    model_files: Vec<PathBuf>,
+    /// The path to save the mixed model to.
+    #[arg(short, required=true)]
+    out_file: PathBuf,
+    /// The path to a data file to evaluate the model.
+    #[arg(short)]
+    eval_file: Option<PathBuf>,
+    /// The weight of the first model, between 0 and 1. The second model 
+    /// gets the rest.
+    #[arg(long, default_value_t=0.5, conflicts_with="dev_file")]
+    lambda: f64,
+    /// Pick the weight of the first model that tags the sentences of this 
+    /// data file most accurately instead.
+    #[arg(long, value_name="FILE")]
+    dev_file: Option<PathBuf>,
+    /// The number of evenly spaced intervals between the weights tried 
+    /// when tuning on a dev file.
+    #[arg(long, value_name="N", default_value_t=10)]
+    steps: usize,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TaggerKind {
+    /// A hidden Markov model decoded with the Viterbi algorithm.
+    Hmm,
+    /// An averaged structured perceptron over word and context features.
+    Perceptron,
+    /// A linear-chain conditional random field trained with SGD.
+    Crf
+}
+
+#[derive(Args)]
+struct CrfArgs {
+    /// Comma-separated CRF feature templates: bias, w[OFFSET], preN[OFFSET], 
+    /// sufN[OFFSET], shape[OFFSET] or affix[OFFSET].
+    #[arg(long, value_delimiter=',', default_value=crf::DEFAULT_FEATURE_TEMPLATES)]
+    templates: Vec<crf::FeatureTemplate>,
+    /// The initial SGD step size of the CRF.
+    #[arg(long, value_name="RATE", default_value_t=crf::DEFAULT_LEARNING_RATE)]
+    learning_rate: f64,
+    /// The strength of the L2 penalty on the CRF weights.
+    #[arg(long, value_name="LAMBDA", default_value_t=crf::DEFAULT_L2)]
+    l2: f64
+}
+
+impl CrfArgs {
+    /// Fails if any CRF setting was given to a tagger that would ignore it.
+    fn reject_crf_only_flags(&self) -> Result<(), Box<dyn Error>> {
+        let flag = [
+            (self.templates != crf::FeatureTemplate::default_templates(), "--templates"),
+            (self.learning_rate != crf::DEFAULT_LEARNING_RATE, "--learning-rate"),
+            (self.l2 != crf::DEFAULT_L2, "--l2")
+        ]
+            .into_iter()
+            .find_map(|(given, flag)| given.then_some(flag));
+
+        match flag {
+            Some(flag) => Err(format!("{flag} is only supported when training CRFs"))?,
+            None => Ok(())
+        }
+    }
+}
+
+#[derive(Args)]
+struct TagMappingArgs {
+    /// Comma-separated tagset mappings to apply to every tag: 
+    /// penn-universal, brown-universal, or the path to a file of FROM TO 
+    /// pairs, one per line.
+    #[arg(long, value_name="MAPPING", value_delimiter=',')]
+    tag_mapping: Vec<String>
+}
+
+impl TagMappingArgs {
+    fn mapping(&self) -> Result<tagset::TagMapping, Box<dyn Error>> {
+        let mut mapping = tagset::TagMapping::default();
+        for name in self.tag_mapping.iter() {
+            mapping.extend(match name.as_str() {
+                "penn-universal" => tagset::TagMapping::penn_to_universal(),
+                "brown-universal" => tagset::TagMapping::brown_to_universal(),
+                path => tagset::TagMapping::from_file(path.into())?
+            });
+        }
+
+        Ok(mapping)
+    }
+}
+
+#[derive(Args)]
+struct ModelArgs {
+    /// Train a second-order (trigram) model using deleted interpolation.
+    #[arg(long)]
+    trigram: bool,
+    /// Only consider the tags observed with a word during training once it 
+    /// has been seen at least N times.
+    #[arg(long, value_name="N")]
+    tag_dictionary_cutoff: Option<usize>,
+    /// Score words unseen in training with a TnT-style model of the tags of 
+    /// rare words ending in each of their last N letters, where rare words 
+    /// are those seen at most --rare-word-threshold times.
+    #[arg(long, value_name="N")]
+    suffix_length: Option<usize>,
+    /// Replace the hard-coded affix classes of unknown words with the N 
+    /// prefixes and suffixes that best predict the tags of hapax words.
+    #[arg(long, value_name="N")]
+    learned_affixes: Option<usize>,
+    /// A file of CLASS RULE lines mapping unknown words to pseudo-word 
+    /// classes, where a rule is a /regex/, prefix:TEXT, suffix:TEXT, 
+    /// contains:TEXT, number or punctuation.
+    #[arg(long, value_name="FILE")]
+    word_class_rules: Option<PathBuf>,
+    /// Keep the case of words instead of folding it.
+    #[arg(long)]
+    case_sensitive: bool,
+    /// Bring words into a Unicode normalization form before folding their 
+    /// case.
+    #[arg(long, value_enum, value_name="FORM")]
+    unicode_normalization: Option<UnicodeForm>,
+    /// Score words by how likely each tag is to be capitalized like them 
+    /// (sentence-initial, inner, all caps or mixed case).
+    #[arg(long)]
+    capitalization_features: bool,
+    /// Let words seen at most N times with a tag stand in for unknown words 
+    /// with that tag.
+    #[arg(long, value_name="N", default_value_t=1)]
+    rare_word_threshold: usize,
+    /// How to smooth the initial, transition and emission distributions: 
+    /// laplace, lidstone:ALPHA, witten-bell, good-turing or absolute:DISCOUNT.
+    #[arg(long, value_name="SPEC", default_value_t=Smoothing::default())]
+    smoothing: Smoothing,
+    /// Smooth the initial tag distribution with SPEC instead.
+    #[arg(long, value_name="SPEC")]
+    initial_smoothing: Option<Smoothing>,
+    /// Smooth the tag transition distributions with SPEC instead.
+    #[arg(long, value_name="SPEC")]
+    transition_smoothing: Option<Smoothing>,
+    /// Smooth the word emission distributions with SPEC instead.
+    #[arg(long, value_name="SPEC")]
+    emission_smoothing: Option<Smoothing>,
+    /// Save the training counts with the model so that `update` can add 
+    /// sentences to it later.
+    #[arg(long)]
+    keep_counts: bool
+}
+
+impl ModelArgs {
+    /// The first of these HMM settings that was given, if any.
+    fn hmm_only_flag(&self) -> Option<&'static str> {
+        [
+            (self.trigram, "--trigram"),
+            (self.tag_dictionary_cutoff.is_some(), "--tag-dictionary-cutoff"),
+            (self.suffix_length.is_some(), "--suffix-length"),
+            (self.learned_affixes.is_some(), "--learned-affixes"),
+            (self.word_class_rules.is_some(), "--word-class-rules"),
+            (self.capitalization_features, "--capitalization-features"),
+            (self.rare_word_threshold != 1, "--rare-word-threshold"),
+            (self.smoothing != Smoothing::default(), "--smoothing"),
+            (self.initial_smoothing.is_some(), "--initial-smoothing"),
+            (self.transition_smoothing.is_some(), "--transition-smoothing"),
+            (self.emission_smoothing.is_some(), "--emission-smoothing"),
+            (self.keep_counts, "--keep-counts")
+        ]
+            .into_iter()
+            .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    /// Fails if any HMM setting was given to a tagger that would ignore it.
+    fn reject_hmm_only_flags(&self) -> Result<(), Box<dyn Error>> {
+        match self.hmm_only_flag() {
+            Some(flag) => Err(format!("{flag} is only supported when training HMMs"))?,
+            None => Ok(())
+        }
+    }
+
+    /// The first of the settings that tuning replaces with its candidates
+    /// that was given, if any.
+    fn tuned_flag(&self) -> Option<&'static str> {
+        [
+            (self.rare_word_threshold != 1, "--rare-word-threshold"),
+            (self.smoothing != Smoothing::default(), "--smoothing"),
+            (self.initial_smoothing.is_some(), "--initial-smoothing"),
+            (self.transition_smoothing.is_some(), "--transition-smoothing"),
+            (self.emission_smoothing.is_some(), "--emission-smoothing")
+        ]
+            .into_iter()
+            .find_map(|(given, flag)| given.then_some(flag))
+    }
+
+    fn normalization_form(&self) -> Option<NormalizationForm> {
+        self.unicode_normalization.map(|form| match form {
+            UnicodeForm::Nfc => NormalizationForm::Nfc,
+            UnicodeForm::Nfkc => NormalizationForm::Nfkc
+        })
+    }
+
+    fn trainer(&self) -> Result<hmm::POSTaggingHMMTrainer, Box<dyn Error>> {
+        let order = if self.trigram { hmm::HMMOrder::Second } else { hmm::HMMOrder::First };
+        let mut trainer = hmm::POSTaggingHMMTrainer::new()
+            .with_order(order)
+            .with_case_sensitivity(self.case_sensitive)
+            .with_capitalization_features(self.capitalization_features)
+            .with_kept_counts(self.keep_counts)
+            .with_rare_word_threshold(self.rare_word_threshold)
+            .with_smoothing(self.smoothing);
+        if let Some(smoothing) = self.initial_smoothing {
+            trainer = trainer.with_initial_smoothing(smoothing);
+        }
+        if let Some(smoothing) = self.transition_smoothing {
+            trainer = trainer.with_transition_smoothing(smoothing);
+        }
+        if let Some(smoothing) = self.emission_smoothing {
+            trainer = trainer.with_emission_smoothing(smoothing);
+        }
+        if let Some(cutoff) = self.tag_dictionary_cutoff {
+            trainer = trainer.with_tag_dictionary_cutoff(cutoff);
+        }
+        if let Some(length) = self.suffix_length {
+            trainer = trainer.with_suffix_length(length);
+        }
+        if let Some(count) = self.learned_affixes {
+            trainer = trainer.with_learned_affixes(count);
+        }
+        if let Some(form) = self.normalization_form() {
+            trainer = trainer.with_normalization_form(form);
+        }
+        if let Some(path) = &self.word_class_rules {
+            trainer = trainer.with_word_class_rules(hmm::WordClassRules::from_file(path.clone())?);
+        }
+        Ok(trainer)
+    }
+}
+
+#[derive(Args)]
+struct TrainEmArgs {
+    /// The path to a pre-trained model to start from.
+    #[arg(short, required_unless_present="data_files", conflicts_with="data_files")]
+    model_file: Option<PathBuf>,
+    /// Paths to tagged data files used to train the starting model.
+    #[arg(short)]
+    data_files: Vec<PathBuf>,
+    /// Paths to files of untagged sentences, one sentence per line.
+    #[arg(short, required=true)]
+    untagged_files: Vec<PathBuf>,
+    /// The path to save the re-estimated model to.
+    #[arg(short, required=true)]
+    out_file: PathBuf,
+    /// The path to a data file to evaluate the model.
+    #[arg(short)]
+    eval_file: Option<PathBuf>,
+    /// The maximum number of EM iterations to run.
+    #[arg(long, default_value_t=10)]
+    iterations: usize,
+    /// Stop once the relative change in log-likelihood falls below this.
+    #[arg(long, default_value_t=1e-4)]
+    threshold: f64,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+#[derive(Args)]
+struct SelfTrainArgs {
+    /// The path to a pre-trained model used to tag the first round. 
+    /// Defaults to a model trained on the data files.
+    #[arg(short)]
+    model_file: Option<PathBuf>,
+    /// Paths to tagged data files that every round is trained on.
+    #[arg(short, required=true)]
+    data_files: Vec<PathBuf>,
+    /// Paths to files of untagged sentences, one sentence per line.
+    #[arg(short, required=true)]
+    untagged_files: Vec<PathBuf>,
+    /// The path to save the self-trained model to.
+    #[arg(short, required=true)]
+    out_file: PathBuf,
+    /// The path to a data file to evaluate the model.
+    #[arg(short)]
+    eval_file: Option<PathBuf>,
+    /// The number of rounds of tagging and retraining to run.
+    #[arg(long, default_value_t=3)]
+    rounds: usize,
+    /// Only keep sentences whose Viterbi log-likelihood per word is at 
+    /// least this.
+    #[arg(long, allow_negative_numbers=true, default_value_t=-8.0)]
+    threshold: f64,
+    #[command(flatten)]
+    model: ModelArgs,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum UnicodeForm {
+    /// Canonical composition, so that accented letters match however they 
+    /// were typed.
+    Nfc,
+    /// Compatibility composition, which also maps full-width characters and 
+    /// ligatures to their plain forms.
+    Nfkc
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Decoder {
+    /// Tag with the single most likely tag sequence.
+    Viterbi,
+    /// Tag each word with its most likely tag under the posterior marginals.
+    Posterior,
+    /// Approximate the most likely tag sequence with a pruned beam search.
+    Beam
+}
+
+#[derive(Args)]
+struct DecodingArgs {
+    /// The decoding strategy used to pick tags.
+    #[arg(long, value_enum, default_value_t=Decoder::Viterbi)]
+    decoder: Decoder,
+    /// The number of states the beam decoder keeps at each word.
+    #[arg(long, value_name="B", default_value_t=8)]
+    beam_width: usize,
+    /// Prune beam states whose log-likelihood trails the best by more than DELTA.
+    #[arg(long, value_name="DELTA")]
+    beam_threshold: Option<f64>
+}
+
+impl DecodingArgs {
+    fn strategy(&self) -> hmm::DecodingStrategy {
+        match self.decoder {
+            Decoder::Viterbi => hmm::DecodingStrategy::Viterbi,
+            Decoder::Posterior => hmm::DecodingStrategy::Posterior,
+            Decoder::Beam => hmm::DecodingStrategy::Beam {
+                width: self.beam_width,
+                threshold: self.beam_threshold
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum VoteKind {
+    /// Every model casts one vote for its tag.
+    Majority,
+    /// Every model casts a vote with the weight given to it.
+    Weighted,
+    /// Sum the log-posteriors of each tag across models.
+    LogPosterior
+}
+
+#[derive(Args)]
+struct EnsembleArgs {
+    /// How several models settle on the tag of each word.
+    #[arg(long, value_enum, default_value_t=VoteKind::Majority)]
+    vote: VoteKind,
+    /// Comma-separated weights of the models for a weighted vote, in the 
+    /// order the models were given.
+    #[arg(long, value_delimiter=',', required_if_eq("vote", "weighted"))]
+    weights: Vec<f64>
+}
+
+impl EnsembleArgs {
+    fn vote(&self) -> ensemble::Vote {
+        match self.vote {
+            VoteKind::Majority => ensemble::Vote::Majority,
+            VoteKind::Weighted => ensemble::Vote::Weighted(self.weights.clone()),
+            VoteKind::LogPosterior => ensemble::Vote::LogPosterior
+        }
+    }
+}
+
+#[derive(Args)]
+struct EvaluateArgs {
+    /// Paths to saved pre-trained models. Several models are combined into 
+    /// an ensemble.
+    #[arg(short, required=true)]
+    model_files: Vec<PathBuf>,
     /// The path to a data file to evaluate the model.
     #[arg(short, required=true)]
     eval_file: PathBuf,
     /// Whether or not to multi-thread the evaluation.
     #[arg(short, default_value="false")]
-    threaded: bool
+    threaded: bool,
+    #[command(flatten)]
+    decoding: DecodingArgs,
+    #[command(flatten)]
+    ensemble: EnsembleArgs,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
 }
 
 #[derive(Args)]
 struct PredictArgs {
-    /// The path to the saved pre-trained model.
+    /// Paths to saved pre-trained models. Several models are combined into 
+    /// an ensemble.
     #[arg(short, required=true)]
-    model_file: PathBuf,
+    model_files: Vec<PathBuf>,
     /// The path to a data file of sentences to predict with. 
     /// Defaults to STDIN if not specified.
     #[arg(short)]
-    predict_file: Option<PathBuf>
+    predict_file: Option<PathBuf>,
+    /// Print the K highest-scoring taggings of each sentence along with 
+    /// their log-likelihoods.
+    #[arg(long, value_name="K")]
+    n_best: Option<usize>,
+    #[command(flatten)]
+    decoding: DecodingArgs,
+    #[command(flatten)]
+    ensemble: EnsembleArgs,
+    #[command(flatten)]
+    tag_mapping: TagMappingArgs
+}
+
+/// A saved model of any kind the CLI knows how to load.
+enum Model {
+    Hmm(Box<hmm::POSTaggingHMM>),
+    Perceptron(perceptron::PerceptronTagger),
+    Crf(crf::CRFTagger),
+    Ensemble(ensemble::Ensemble<Model>),
+    Mapped(Box<tagset::MappedTagger<Model>>)
+}
+
+impl Model {
+    /// The HMM behind a single model, whether or not its tags are mapped.
+    fn hmm(&self) -> Option<&hmm::POSTaggingHMM> {
+        match self {
+            Model::Hmm(model) => Some(model),
+            Model::Mapped(model) => model.inner().hmm(),
+            _ => None
+        }
+    }
+
+    /// Decodes every HMM in the model with `strategy`, including those of an
+    /// ensemble, and returns whether there were any.
+    fn set_decoding_strategy(&mut self, strategy: hmm::DecodingStrategy) -> bool {
+        match self {
+            Model::Hmm(model) => {
+                model.set_decoding_strategy(strategy);
+                true
+            },
+            Model::Mapped(model) => model.inner_mut().set_decoding_strategy(strategy),
+            Model::Ensemble(model) => {
+                let mut any = false;
+                for member in model.members_mut() {
+                    any |= member.set_decoding_strategy(strategy);
+                }
+                any
+            },
+            _ => false
+        }
+    }
+
+    /// Tags `sentence`, failing if a member of an ensemble does not tag
+    /// every token of it.
+    fn try_tag(&self, sentence: &[String]) -> Result<ensemble::Tagging, Box<dyn Error>> {
+        match self {
+            Model::Ensemble(model) => model.try_tag(sentence),
+            _ => Ok(self.tag(sentence))
+        }
+    }
+}
+
+impl Tagger for Model {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        match self {
+            Model::Hmm(model) => model.tag(sentence),
+            Model::Perceptron(model) => model.tag(sentence),
+            Model::Crf(model) => model.tag(sentence),
+            Model::Ensemble(model) => model.tag(sentence),
+            Model::Mapped(model) => model.tag(sentence)
+        }
+    }
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        match self {
+            Model::Hmm(model) => model.tag_posteriors(sentence),
+            Model::Perceptron(model) => model.tag_posteriors(sentence),
+            Model::Crf(model) => model.tag_posteriors(sentence),
+            Model::Ensemble(model) => model.tag_posteriors(sentence),
+            Model::Mapped(model) => model.tag_posteriors(sentence)
+        }
+    }
+}
+
+fn load_model(path: PathBuf) -> Result<Model, Box<dyn Error>> {
+    let mut header: [u8; 4] = [0; 4];
+    File::open(&path)?.read_exact(&mut header)?;
+
+    match header {
+        perceptron::PerceptronTagger::FILE_HEADER => Ok(Model::Perceptron(perceptron::PerceptronTagger::from_file(path)?)),
+        crf::CRFTagger::FILE_HEADER => Ok(Model::Crf(crf::CRFTagger::from_file(path)?)),
+        _ => Ok(Model::Hmm(Box::new(hmm::POSTaggingHMM::from_file(path)?)))
+    }
+}
+
+/// Loads a single model, or an ensemble of them when given several, decoding
+/// any HMMs among them with `strategy`. The tags of every model are mapped
+/// before they vote.
+fn load_models(
+    paths: Vec<PathBuf>,
+    strategy: hmm::DecodingStrategy,
+    vote: ensemble::Vote,
+    mapping: &tagset::TagMapping
+) -> Result<Model, Box<dyn Error>> {
+    let mut models = paths
+        .into_iter()
+        .map(load_model)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for model in models.iter_mut() {
+        model.set_decoding_strategy(strategy);
+    }
+
+    if !mapping.is_empty() {
+        models = models
+            .into_iter()
+            .map(|model| Model::Mapped(Box::new(tagset::MappedTagger::new(model, mapping.clone()))))
+            .collect();
+    }
+
+    if models.len() == 1 {
+        return Ok(models.pop().unwrap());
+    }
+
+    Ok(Model::Ensemble(ensemble::Ensemble::new(models, vote)?))
 }
 
 fn print_input_identifier() {
@@ -79,58 +696,368 @@ fn preprocess_sentence(s: &mut String) {
     }
 }
 
-fn predict_and_fmt(model: &hmm::POSTaggingHMM, sentence: &str) {
-    let sentence: Vec<String> = sentence
-        .split_whitespace()
-        .map(str::to_string)
-        .collect();
-    let (_, tags): (Vec<_>, Vec<_>) = model.predict(sentence.clone()).into_iter().unzip();
-
-    let output = sentence.into_iter()
-        .zip(tags.into_iter())
+fn fmt_tagging(sentence: &[String], tags: &[String]) -> String {
+    sentence.iter()
+        .zip(tags)
         .map(|(w, t)| format!("{}={}", w, t))
         .collect::<Vec<_>>()
-        .join(" ");
+        .join(" ")
+}
+
+fn predict_and_fmt(model: &Model, sentence: &[String]) {
+    match model.try_tag(sentence) {
+        Ok((tags, _)) => println!("{}", fmt_tagging(sentence, &tags)),
+        Err(e) => eprintln!("Failed to tag sentence: {e}")
+    }
+}
 
-    println!("{}", output);
+fn n_best_and_fmt(model: &hmm::POSTaggingHMM, sentence: &[String], k: usize, mapping: &tagset::TagMapping) {
+    for (score, tagging) in model.predict_n_best(sentence.to_vec(), k) {
+        let tags: Vec<String> = mapping
+            .map_sentence(tagging)
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        println!("{:.3}\t{}", score, fmt_tagging(sentence, &tags));
+    }
+}
+
+/// Reads the sentences of a tagged data file with their tags mapped.
+fn read_mapped_sentences(path: PathBuf, mapping: &tagset::TagMapping) -> Result<Vec<TaggedSentence>, Box<dyn Error>> {
+    let sentences = hmm::read_tagged_sentences(path)?
+        .into_iter()
+        .map(|sentence| mapping.map_sentence(sentence))
+        .collect();
+
+    Ok(sentences)
+}
+
+fn train_hmm(train_args: TrainArgs) -> Result<(), Box<dyn Error>> {
+    train_args.crf.reject_crf_only_flags()?;
+    if train_args.epochs.is_some() {
+        Err("--epochs is only supported when training perceptrons and CRFs")?
+    }
+    let start = Instant::now();
+    let trainer = train_args.resume
+        .into_iter()
+        .try_fold(train_args.model.trainer()?, |t, f| t.merge(hmm::POSTaggingHMMTrainer::from_file(f)?));
+
+    let mapping = train_args.tag_mapping.mapping()?;
+    let checkpoint = train_args.checkpoint;
+    let data_files = train_args.data_files;
+    let train_result = trainer
+        .and_then(|trainer| data_files.into_iter().try_fold(trainer, |t, f| {
+            Ok(t.train_sentences(read_mapped_sentences(f, &mapping)?))
+        }))
+        .and_then(|trainer| {
+            if let Some(f) = checkpoint {
+                trainer.save(f)?;
+            }
+            trainer.finalize()
+        });
+
+    if let Err(e) = train_result {
+        eprintln!("Failed to train model: {e:?}");
+        std::process::exit(1);
+    }
+
+    let duration = Instant::now() - start;
+    println!("Model training took {:.03}s", duration.as_secs_f64());
+    
+    let model = train_result?;
+    if let Some([l1, l2, l3]) = model.interpolation_weights() {
+        println!("Interpolation weights: unigram={l1:.3} bigram={l2:.3} trigram={l3:.3}");
+    }
+    model.save(train_args.out_file)?;
+
+    if let Some(f) = train_args.eval_file {
+        hmm::evaluate_mapped(&model, f, &mapping)?;
+    }
+
+    Ok(())
+}
+
+fn train_perceptron(train_args: TrainArgs) -> Result<(), Box<dyn Error>> {
+    train_args.model.reject_hmm_only_flags()?;
+    train_args.crf.reject_crf_only_flags()?;
+    let start = Instant::now();
+    let mut trainer = perceptron::PerceptronTrainer::new()
+        .with_case_sensitivity(train_args.model.case_sensitive);
+    if let Some(epochs) = train_args.epochs {
+        trainer = trainer.with_iterations(epochs);
+    }
+    if let Some(form) = train_args.model.normalization_form() {
+        trainer = trainer.with_normalization_form(form);
+    }
+
+    let mapping = train_args.tag_mapping.mapping()?;
+    let train_result = train_args.data_files
+        .into_iter()
+        .try_fold(trainer, |t, f| Ok(t.train_sentences(read_mapped_sentences(f, &mapping)?)))
+        .and_then(perceptron::PerceptronTrainer::finalize);
+
+    if let Err(e) = train_result {
+        eprintln!("Failed to train model: {e:?}");
+        std::process::exit(1);
+    }
+
+    let duration = Instant::now() - start;
+    println!("Model training took {:.03}s", duration.as_secs_f64());
+
+    let model = train_result?;
+    println!("Kept {} features", model.num_features());
+    model.save(train_args.out_file)?;
+
+    if let Some(f) = train_args.eval_file {
+        hmm::evaluate_mapped(&model, f, &mapping)?;
+    }
+
+    Ok(())
+}
+
+fn train_crf(train_args: TrainArgs) -> Result<(), Box<dyn Error>> {
+    train_args.model.reject_hmm_only_flags()?;
+    let start = Instant::now();
+    let mut trainer = crf::CRFTrainer::new()
+        .with_templates(train_args.crf.templates)
+        .with_learning_rate(train_args.crf.learning_rate)
+        .with_l2(train_args.crf.l2)
+        .with_case_sensitivity(train_args.model.case_sensitive);
+    if let Some(epochs) = train_args.epochs {
+        trainer = trainer.with_epochs(epochs);
+    }
+    if let Some(form) = train_args.model.normalization_form() {
+        trainer = trainer.with_normalization_form(form);
+    }
+
+    let mapping = train_args.tag_mapping.mapping()?;
+    let train_result = train_args.data_files
+        .into_iter()
+        .try_fold(trainer, |t, f| Ok(t.train_sentences(read_mapped_sentences(f, &mapping)?)))
+        .and_then(|t| t.finalize_with_progress(|i, ll| {
+            println!("Epoch {}: log-likelihood = {:.3}", i, ll);
+        }));
+
+    if let Err(e) = train_result {
+        eprintln!("Failed to train model: {e:?}");
+        std::process::exit(1);
+    }
+
+    let duration = Instant::now() - start;
+    println!("Model training took {:.03}s", duration.as_secs_f64());
+
+    let model = train_result?;
+    println!("Learned weights for {} features", model.num_features());
+    model.save(train_args.out_file)?;
+
+    if let Some(f) = train_args.eval_file {
+        hmm::evaluate_mapped(&model, f, &mapping)?;
+    }
+
+    Ok(())
+}
+
+fn tune_hmm(tune_args: TuneArgs) -> Result<(), Box<dyn Error>> {
+    if let Some(flag) = tune_args.model.tuned_flag() {
+        Err(format!("{flag} is replaced by the candidates of --smoothings and --rare-word-thresholds when tuning"))?
+    }
+
+    let start = Instant::now();
+    let mapping = tune_args.tag_mapping.mapping()?;
+    let trainer = tune_args.data_files
+        .into_iter()
+        .try_fold(tune_args.model.trainer()?, |t, f| read_mapped_sentences(f, &mapping).map(|s| t.train_sentences(s)))?;
+
+    let duration = Instant::now() - start;
+    println!("Counting the training data took {:.03}s", duration.as_secs_f64());
+
+    let search = match tune_args.search {
+        SearchKind::Grid => hmm::Search::Grid,
+        SearchKind::Random => hmm::Search::Random { trials: tune_args.trials, seed: tune_args.seed }
+    };
+    let candidates = hmm::search_candidates(&tune_args.smoothings, &tune_args.rare_word_thresholds, search);
+    let num_candidates = candidates.len();
+
+    let start = Instant::now();
+    let mut tried = 0;
+    let (ranking, model) = hmm::tune_hyperparameters(&trainer, candidates, tune_args.dev_file, &mapping, |candidate, accuracy| {
+        tried += 1;
+        println!(
+            "Candidate {}/{}: smoothing={} rare-word-threshold={} accuracy={:.3}%",
+            tried, num_candidates, candidate.smoothing, candidate.rare_word_threshold, accuracy
+        );
+    })?;
+
+    let duration = Instant::now() - start;
+    println!("Tuning took {:.03}s", duration.as_secs_f64());
+
+    println!("rank\tsmoothing\trare-word-threshold\taccuracy");
+    for (rank, (candidate, accuracy)) in ranking.iter().enumerate() {
+        println!("{}\t{}\t{}\t{:.3}%", rank + 1, candidate.smoothing, candidate.rare_word_threshold, accuracy);
+    }
+
+    let (best, _) = ranking[0];
+    println!("Picked smoothing = {}, rare word threshold = {}", best.smoothing, best.rare_word_threshold);
+    model.save(tune_args.out_file)?;
+
+    if let Some(f) = tune_args.eval_file {
+        hmm::evaluate_mapped(&model, f, &mapping)?;
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Cli::parse();
 
     match args.command {
-        Command::Train(train_args) => {
+        Command::Train(train_args) => match train_args.tagger {
+            TaggerKind::Hmm => train_hmm(train_args)?,
+            _ if train_args.checkpoint.is_some() || !train_args.resume.is_empty() => {
+                Err("Checkpoints are only supported when training HMMs")?
+            },
+            TaggerKind::Perceptron => train_perceptron(train_args)?,
+            TaggerKind::Crf => train_crf(train_args)?
+        },
+        Command::Tune(tune_args) => tune_hmm(tune_args)?,
+        Command::MergeCounts(merge_args) => {
+            let mut checkpoint_files = merge_args.checkpoint_files.into_iter();
+            let first = hmm::POSTaggingHMMTrainer::from_file(checkpoint_files.next().unwrap())?;
+            let merged = checkpoint_files
+                .try_fold(first, |t, f| t.merge(hmm::POSTaggingHMMTrainer::from_file(f)?))?;
+
+            merged.save(merge_args.out_file)?;
+        },
+        Command::Update(update_args) => {
+            let mut model = hmm::POSTaggingHMM::from_file(update_args.model_file)?;
+            let mapping = update_args.tag_mapping.mapping()?;
+            let sentences = update_args.data_files
+                .into_iter()
+                .map(|f| read_mapped_sentences(f, &mapping))
+                .collect::<Result<Vec<_>, _>>()?;
+
             let start = Instant::now();
-            let train_result = train_args.data_files
+            model.update(sentences.into_iter().flatten())?;
+
+            let duration = Instant::now() - start;
+            println!("Model update took {:.03}s", duration.as_secs_f64());
+            model.save(update_args.out_file)?;
+
+            if let Some(f) = update_args.eval_file {
+                hmm::evaluate_mapped(&model, f, &mapping)?;
+            }
+        },
+        Command::Mix(mix_args) => {
+            let first = hmm::POSTaggingHMM::from_file(mix_args.model_files[0].clone())?;
+            let second = hmm::POSTaggingHMM::from_file(mix_args.model_files[1].clone())?;
+            let mapping = mix_args.tag_mapping.mapping()?;
+
+            let model = match mix_args.dev_file {
+                Some(f) => {
+                    println!("lambda\taccuracy");
+                    let (lambda, model) = hmm::tune_mixture_weight(&first, &second, f, mix_args.steps, &mapping, |lambda, accuracy| {
+                        println!("{:.3}\t{:.3}%", lambda, accuracy);
+                    })?;
+                    println!("Picked lambda = {:.3}", lambda);
+                    model
+                },
+                None => first.mix(&second, mix_args.lambda)?
+            };
+            model.save(mix_args.out_file)?;
+
+            if let Some(f) = mix_args.eval_file {
+                hmm::evaluate_mapped(&model, f, &mapping)?;
+            }
+        },
+        Command::TrainEm(em_args) => {
+            let mapping = em_args.tag_mapping.mapping()?;
+            let em_trainer = match em_args.model_file {
+                Some(f) => hmm::BaumWelchTrainer::new(hmm::POSTaggingHMM::from_file(f)?)?,
+                None => {
+                    let trainer = em_args.data_files
+                        .into_iter()
+                        .try_fold(hmm::POSTaggingHMMTrainer::new(), |t, f| read_mapped_sentences(f, &mapping).map(|s| t.train_sentences(s)))?;
+                    hmm::BaumWelchTrainer::from_trainer(trainer)?
+                }
+            };
+
+            let em_trainer = em_args.untagged_files
                 .into_iter()
-                .try_fold(hmm::POSTaggingHMMTrainer::new(), |t, f| t.train(f))
-                .and_then(hmm::POSTaggingHMMTrainer::finalize);
+                .try_fold(em_trainer, |t, f| t.train(f))?;
+
+            let start = Instant::now();
+            let model = em_trainer.run(em_args.iterations, em_args.threshold, |i, ll| {
+                println!("Iteration {}: log-likelihood = {:.3}", i, ll);
+            });
 
-            if let Err(e) = train_result {
-                eprintln!("Failed to train model: {e:?}");
-                std::process::exit(1);
+            let duration = Instant::now() - start;
+            println!("Model re-estimation took {:.03}s", duration.as_secs_f64());
+            model.save(em_args.out_file)?;
+
+            if let Some(f) = em_args.eval_file {
+                hmm::evaluate_mapped(&model, f, &mapping)?;
+            }
+        },
+        Command::SelfTrain(self_train_args) => {
+            let mapping = self_train_args.tag_mapping.mapping()?;
+            let trainer = self_train_args.data_files
+                .into_iter()
+                .try_fold(self_train_args.model.trainer()?, |t, f| read_mapped_sentences(f, &mapping).map(|s| t.train_sentences(s)))?;
+
+            let mut self_trainer = hmm::SelfTrainer::new(trainer);
+            if let Some(f) = self_train_args.model_file {
+                self_trainer = self_trainer.with_model(hmm::POSTaggingHMM::from_file(f)?);
             }
 
+            let self_trainer = self_train_args.untagged_files
+                .into_iter()
+                .try_fold(self_trainer, |t, f| t.train(f))?;
+
+            let start = Instant::now();
+            let model = self_trainer.run(self_train_args.rounds, self_train_args.threshold, |i, kept, total| {
+                println!("Round {}: kept {} of {} sentences", i, kept, total);
+            })?;
+
             let duration = Instant::now() - start;
-            println!("Model training took {:.03}s", duration.as_secs_f64());
-            
-            let model = train_result?;
-            model.save(train_args.out_file)?;
+            println!("Model self-training took {:.03}s", duration.as_secs_f64());
+            model.save(self_train_args.out_file)?;
 
-            if let Some(f) = train_args.eval_file {
-                hmm::evaluate(&model, f)?;
+            if let Some(f) = self_train_args.eval_file {
+                hmm::evaluate_mapped(&model, f, &mapping)?;
             }
         },
         Command::Evaluate(eval_args) => {
-            let model = hmm::POSTaggingHMM::from_file(eval_args.model_file)?;
-            if eval_args.threaded {
-                hmm::par_evaluate(&model, eval_args.eval_file)?;
-            } else {
-                hmm::evaluate(&model, eval_args.eval_file)?;
+            let strategy = eval_args.decoding.strategy();
+            let mapping = eval_args.tag_mapping.mapping()?;
+            let mut model = load_models(eval_args.model_files, strategy, eval_args.ensemble.vote(), &mapping)?;
+            let run = |model: &Model| {
+                if eval_args.threaded {
+                    hmm::par_evaluate_mapped(model, eval_args.eval_file.clone(), &mapping)
+                } else {
+                    hmm::evaluate_mapped(model, eval_args.eval_file.clone(), &mapping)
+                }
+            };
+
+            let accuracy = run(&model)?;
+
+            if matches!(strategy, hmm::DecodingStrategy::Beam { .. }) && model.set_decoding_strategy(hmm::DecodingStrategy::Viterbi) {
+                println!("Evaluating exact Viterbi decoding for comparison...");
+                let exact_accuracy = run(&model)?;
+                println!("Beam search gave up {:.03}% accuracy against exact Viterbi", exact_accuracy - accuracy);
             }
         },
         Command::Predict(predict_args) => {
-            let model = hmm::POSTaggingHMM::from_file(predict_args.model_file)?;
+            let mapping = predict_args.tag_mapping.mapping()?;
+            let model = load_models(
+                predict_args.model_files,
+                predict_args.decoding.strategy(),
+                predict_args.ensemble.vote(),
+                &mapping
+            )?;
+            if predict_args.n_best.is_some() && model.hmm().is_none() {
+                Err("--n-best is only supported by a single HMM model")?
+            }
+
             let is_file = predict_args.predict_file.is_some();
             let mut input: Box<dyn io::BufRead> = match predict_args.predict_file {
                 Some(f) => Box::new(BufReader::new(File::open(f)?)),
@@ -146,7 +1073,15 @@ fn main() -> Result<(), Box<dyn Error>> {
                 if is_file { println!("{}", buf); }
 
                 preprocess_sentence(&mut buf);
-                predict_and_fmt(&model, &buf);
+                let sentence: Vec<String> = buf
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect();
+
+                match (model.hmm(), predict_args.n_best) {
+                    (Some(hmm_model), Some(k)) => n_best_and_fmt(hmm_model, &sentence, k, &mapping),
+                    _ => predict_and_fmt(&model, &sentence)
+                }
                 print_input_identifier();
                 buf.clear();
             }
@@ -1,4 +1,10 @@
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_properties::{GeneralCategory, GeneralCategoryGroup, UnicodeGeneralCategory};
+use std::borrow::Cow;
+
 pub const END_TAG: &str = "END";
+pub const START_TAG: &str = "START";
 const TAG_DELIMITER: char = '=';
 const SENTENCE_DELIMITER: char = ' ';
 const DELIMITER_REPLACEMENT: &str = "/";
@@ -31,14 +37,101 @@ const ARTIFICIAL_TAG_PREFIXES: [(&str, &str); 9] = [
 pub type TaggedWord = (String, String);
 pub type TaggedSentence = Vec<TaggedWord>;
 
+/// A Unicode normalization form applied to words before case folding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NormalizationForm {
+    /// Canonical composition, so that an accented letter is the same word
+    /// whether it was typed precomposed or with a combining accent.
+    Nfc,
+    /// Compatibility composition, which also maps full-width characters and
+    /// ligatures to their plain forms.
+    Nfkc
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaseFolding {
+    None,
+    /// Lowercases ASCII letters only, as models trained before full case
+    /// folding did.
+    Ascii,
+    #[default]
+    Full
+}
+
+/// How words are normalized before a model counts them or looks them up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Normalizer {
+    pub form: Option<NormalizationForm>,
+    pub case_folding: CaseFolding
+}
+
+impl Normalizer {
+    pub fn normalize<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        let word: Cow<'a, str> = match self.form {
+            Some(NormalizationForm::Nfc) => Cow::Owned(word.nfc().collect()),
+            Some(NormalizationForm::Nfkc) => Cow::Owned(word.nfkc().collect()),
+            None => Cow::Borrowed(word)
+        };
+
+        match self.case_folding {
+            CaseFolding::None => word,
+            CaseFolding::Ascii => Cow::Owned(word.to_ascii_lowercase()),
+            CaseFolding::Full => Cow::Owned(fold_case(&word))
+        }
+    }
+}
+
+/// Full Unicode case folding, so that "Straße" and "STRASSE" are the same
+/// word. Lowercasing the uppercase form of every character folds it, which
+/// also folds the characters that have no single lowercase form, such as "ß"
+/// and "ﬁ", except for three cases that are handled first: the dotless "ı"
+/// folds to itself rather than to "i", the capital "ẞ" folds to "ss" like
+/// "ß", and Cherokee folds to its uppercase letters. Characters are folded
+/// one by one, so a final "ς" folds to "σ" as it does anywhere else.
+pub fn fold_case(word: &str) -> String {
+    if word.is_ascii() {
+        return word.to_ascii_lowercase();
+    }
+
+    let mut folded = String::with_capacity(word.len());
+    for c in word.chars() {
+        match c {
+            'ı' => folded.push(c),
+            'ẞ' => folded.push_str("ss"),
+            '\u{13A0}'..='\u{13FD}' | '\u{AB70}'..='\u{ABBF}' => folded.extend(c.to_uppercase()),
+            _ => folded.extend(c.to_uppercase().flat_map(char::to_lowercase))
+        }
+    }
+
+    folded
+}
+
+/// Whether `c` is a digit or other number in any script.
+pub fn is_number(c: char) -> bool {
+    c.general_category_group() == GeneralCategoryGroup::Number
+}
+
+pub fn is_punctuation(c: char) -> bool {
+    c.general_category_group() == GeneralCategoryGroup::Punctuation
+}
+
+/// Whether `c` is a hyphen or dash of any kind.
+pub fn is_dash(c: char) -> bool {
+    c.general_category() == GeneralCategory::DashPunctuation
+}
+
+/// Whether more than half of the characters of `word` are numbers.
+pub fn is_mostly_numeric(word: &str) -> bool {
+    word.chars().filter(|&c| is_number(c)).count() > word.chars().count() / 2
+}
+
 pub fn extract_word_and_tag(sentence: &str) -> Vec<(String, String)> {
     sentence
         .split(SENTENCE_DELIMITER)
-        .into_iter()
         .map(|w| (w, w.matches(TAG_DELIMITER).count()))
         .map(|(w, _)| {
             let c: Vec<&str> = w.split(TAG_DELIMITER).collect();
-            (c[..c.len() - 1].join(DELIMITER_REPLACEMENT).to_ascii_lowercase(), c[c.len() - 1].to_string())
+            (c[..c.len() - 1].join(DELIMITER_REPLACEMENT), c[c.len() - 1].to_string())
         })
         .collect()
 }
@@ -46,30 +139,110 @@ pub fn extract_word_and_tag(sentence: &str) -> Vec<(String, String)> {
 pub fn get_matching_artificial_tag(word: &str) -> Option<&'static str> {
     for (suffix, tag) in ARTIFICIAL_TAG_SUFFIXES {
         if word.ends_with(suffix) {
-            return Some(tag.into());
+            return Some(tag);
         }
     }
 
     for (prefix, tag) in ARTIFICIAL_TAG_PREFIXES {
         if word.starts_with(prefix) {
-            return Some(tag.into());
+            return Some(tag);
         }
     }
 
-    let num_digits = word
-        .chars()
-        .filter(char::is_ascii_digit)
-        .count();
+    get_matching_shape_tag(word)
+}
 
-    if num_digits > word.len() / 2 {
+/// The artificial tag of words made up mostly of numbers or containing
+/// dashes.
+pub fn get_matching_shape_tag(word: &str) -> Option<&'static str> {
+    if is_mostly_numeric(word) {
         return Some("IS-A-NUMBER");
-    } else if word.chars().any(|c| c == '-') {
+    } else if word.chars().any(is_dash) {
         return Some("HAS-MANY-DASHES");
     }
 
     None
 }
 
+/// The artificial tags of every listed suffix and prefix of `word`.
+pub fn get_matching_affix_tags(word: &str) -> Vec<&'static str> {
+    let suffixes = ARTIFICIAL_TAG_SUFFIXES
+        .iter()
+        .filter(|(suffix, _)| word.ends_with(suffix));
+    let prefixes = ARTIFICIAL_TAG_PREFIXES
+        .iter()
+        .filter(|(prefix, _)| word.starts_with(prefix));
+
+    suffixes
+        .chain(prefixes)
+        .map(|&(_, tag)| tag)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capitalization {
+    NoCapitals,
+    /// A capitalized first word of a sentence.
+    InitialCapital,
+    /// A capitalized word anywhere else in a sentence.
+    InnerCapital,
+    AllCapitals,
+    MixedCase
+}
+
+impl Capitalization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capitalization::NoCapitals => "NO-CAPS",
+            Capitalization::InitialCapital => "INITIAL-CAP",
+            Capitalization::InnerCapital => "INNER-CAP",
+            Capitalization::AllCapitals => "ALL-CAPS",
+            Capitalization::MixedCase => "MIXED-CASE"
+        }
+    }
+}
+
+/// Classifies how `word` is capitalized, telling a capitalized first word of
+/// a sentence apart from one elsewhere since only the latter hints at a
+/// proper noun.
+pub fn get_capitalization(word: &str, sentence_initial: bool) -> Capitalization {
+    let letters: Vec<char> = word.chars().filter(|c| c.is_alphabetic()).collect();
+    let capitals = letters.iter().filter(|c| c.is_uppercase()).count();
+
+    match letters.first() {
+        _ if capitals == 0 => Capitalization::NoCapitals,
+        Some(first) if first.is_uppercase() && capitals == 1 => match sentence_initial {
+            true => Capitalization::InitialCapital,
+            false => Capitalization::InnerCapital
+        },
+        _ if capitals == letters.len() => Capitalization::AllCapitals,
+        _ => Capitalization::MixedCase
+    }
+}
+
+/// Maps letters to `x`/`X` and digits to `d`, collapsing repeated characters,
+/// so that "1,000" becomes "d,d" and "Well-Known" becomes "Xx-Xx".
+pub fn get_word_shape(word: &str) -> String {
+    let mut shape = String::new();
+    for c in word.chars() {
+        let class = if is_number(c) {
+            'd'
+        } else if c.is_uppercase() {
+            'X'
+        } else if c.is_alphabetic() {
+            'x'
+        } else {
+            c
+        };
+
+        if !shape.ends_with(class) {
+            shape.push(class);
+        }
+    }
+
+    shape
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,4 +286,58 @@ mod tests {
     fn test_no_matches() {
         assert_eq!(get_matching_artificial_tag("blahblahblah"), None);
     }
+
+    #[test]
+    fn test_every_affix_matches() {
+        assert_eq!(get_matching_affix_tags("reprocessing"), vec!["SUFF-ING", "PREF-RE"]);
+        assert!(get_matching_affix_tags("blahblahblah").is_empty());
+    }
+
+    #[test]
+    fn test_capitalizations() {
+        assert_eq!(get_capitalization("Iraq", true), Capitalization::InitialCapital);
+        assert_eq!(get_capitalization("Iraq", false), Capitalization::InnerCapital);
+        assert_eq!(get_capitalization("US", false), Capitalization::AllCapitals);
+        assert_eq!(get_capitalization("iPhone", false), Capitalization::MixedCase);
+        assert_eq!(get_capitalization("us", true), Capitalization::NoCapitals);
+        assert_eq!(get_capitalization("1,000", false), Capitalization::NoCapitals);
+    }
+
+    #[test]
+    fn test_case_folding_special_cases() {
+        // Lowercasing the uppercase form would fold these differently.
+        assert_eq!(fold_case("STRAẞE"), "strasse");
+        assert_eq!(fold_case("ırmak"), "ırmak");
+        assert_ne!(fold_case("ırmak"), fold_case("IRMAK"));
+        assert_eq!(fold_case("ᏣᎳᎩ"), fold_case("ꮳꮃꭹ"));
+        assert_eq!(fold_case("ꮳꮃꭹ"), "ᏣᎳᎩ");
+        // Lowercasing a whole word would keep the final sigma.
+        assert_eq!(fold_case("ΟΔΟΣ"), "οδοσ");
+        assert_eq!(fold_case("οδος"), "οδοσ");
+    }
+
+    #[test]
+    fn test_unicode_words() {
+        assert_eq!(get_matching_artificial_tag("２０２４"), Some("IS-A-NUMBER"));
+        assert_eq!(get_matching_artificial_tag("été"), None);
+        assert_eq!(get_matching_artificial_tag("Rhein–Main"), Some("HAS-MANY-DASHES"));
+        assert_eq!(get_matching_artificial_tag("«"), None);
+
+        let nfkc = Normalizer { form: Some(NormalizationForm::Nfkc), case_folding: CaseFolding::Full };
+        assert_eq!(nfkc.normalize("Ｓｔｒａßｅ"), "strasse");
+        assert_eq!(Normalizer::default().normalize("ÉTÉ"), "été");
+        assert_eq!(fold_case("Straße"), fold_case("STRASSE"));
+        assert_eq!(fold_case("ﬁne"), "fine");
+
+        let nfc = Normalizer { form: Some(NormalizationForm::Nfc), case_folding: CaseFolding::None };
+        assert_eq!(nfc.normalize("E\u{301}te\u{301}"), "\u{c9}t\u{e9}");
+        assert_eq!(get_word_shape("１,０００"), "d,d");
+    }
+
+    #[test]
+    fn test_word_shapes() {
+        assert_eq!(get_word_shape("1,000"), "d,d");
+        assert_eq!(get_word_shape("Well-Known"), "Xx-Xx");
+        assert_eq!(get_word_shape("cat"), "x");
+    }
 }
@@ -0,0 +1,28 @@
+use crate::nlp::{get_matching_affix_tags, get_word_shape};
+use crate::linear::{neighbour, prefix, suffix};
+
+/// The features of the word at `index` that do not depend on any tag. The
+/// word features read the case-folded `words`, while the spelling features
+/// read the `tokens` as written.
+pub(in crate::perceptron) fn token_features(words: &[String], tokens: &[String], index: usize) -> Vec<String> {
+    let token = &tokens[index];
+    let neighbour_word = |offset: isize| neighbour(words, index, offset);
+    let neighbour_token = |offset: isize| neighbour(tokens, index, offset);
+
+    let mut features = vec![
+        "bias".to_string(),
+        format!("w={}", words[index]),
+        format!("suf3={}", suffix(token, 3)),
+        format!("pre1={}", prefix(token, 1)),
+        format!("shape={}", get_word_shape(token)),
+        format!("w-1={}", neighbour_word(-1)),
+        format!("w-2={}", neighbour_word(-2)),
+        format!("w+1={}", neighbour_word(1)),
+        format!("w+2={}", neighbour_word(2)),
+        format!("suf3-1={}", suffix(neighbour_token(-1), 3)),
+        format!("suf3+1={}", suffix(neighbour_token(1), 3))
+    ];
+
+    features.extend(get_matching_affix_tags(token).into_iter().map(|tag| format!("affix={tag}")));
+    features
+}
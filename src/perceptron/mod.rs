@@ -0,0 +1,6 @@
+mod features;
+mod model;
+mod trainer;
+
+pub use model::*;
+pub use trainer::*;
@@ -0,0 +1,87 @@
+use super::features::token_features;
+use crate::linear::{self, viterbi, words_and_tokens};
+use crate::nlp::{Normalizer, TaggedSentence};
+use crate::Tagger;
+use std::{error::Error, collections::HashMap, path::PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// An averaged structured perceptron tagger (Collins, 2002) scoring words by
+/// their spelling and context and tag sequences by their transitions.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PerceptronTagger {
+    tag_set: Vec<String>,
+    feature_indices: HashMap<String, usize>,
+    weights: Vec<f64>,
+    transitions: Vec<f64>,
+    normalizer: Normalizer
+}
+
+impl PerceptronTagger {
+    pub const FILE_HEADER: [u8; 4] = *b"VAP1";
+
+    pub(in crate::perceptron) fn new(
+        tag_set: Vec<String>,
+        feature_indices: HashMap<String, usize>,
+        weights: Vec<f64>,
+        transitions: Vec<f64>,
+        normalizer: Normalizer
+    ) -> Self {
+        Self { tag_set, feature_indices, weights, transitions, normalizer }
+    }
+
+    pub fn tag_set(&self) -> &[String] {
+        &self.tag_set
+    }
+
+    pub fn num_features(&self) -> usize {
+        self.feature_indices.len()
+    }
+
+    /// How words are normalized before their features are extracted.
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        linear::from_file(Self::FILE_HEADER, path)
+    }
+
+    pub fn save(&self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        linear::save(self, Self::FILE_HEADER, path)
+    }
+
+    pub fn predict(&self, sentence: Vec<String>) -> TaggedSentence {
+        let tags = self.decode(&sentence);
+
+        sentence
+            .into_iter()
+            .zip(tags)
+            .map(|(word, tag)| (word, self.tag_set[tag].clone()))
+            .collect()
+    }
+
+    fn decode(&self, sentence: &[String]) -> Vec<usize> {
+        let (words, tokens) = words_and_tokens(&self.normalizer, sentence);
+        let features: Vec<Vec<usize>> = (0..sentence.len())
+            .map(|i| {
+                token_features(&words, &tokens, i)
+                    .iter()
+                    .filter_map(|f| self.feature_indices.get(f).copied())
+                    .collect()
+            })
+            .collect();
+
+        viterbi(&self.weights, &self.transitions, self.tag_set.len(), &features)
+    }
+}
+
+impl Tagger for PerceptronTagger {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        let tags = self.decode(sentence)
+            .into_iter()
+            .map(|tag| self.tag_set[tag].clone())
+            .collect();
+
+        (tags, None)
+    }
+}
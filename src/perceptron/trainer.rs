@@ -0,0 +1,114 @@
+use super::model::PerceptronTagger;
+use super::features::token_features;
+use crate::linear::{viterbi, LearningRule, LinearTrainer, TrainingSet};
+use std::collections::HashMap;
+use std::error::Error;
+
+const DEFAULT_ITERATIONS: usize = 5;
+
+/// Perceptron weights along with the running sums needed to average them
+/// over every training step without revisiting untouched weights.
+struct AveragedWeights {
+    weights: Vec<f64>,
+    updates: Vec<f64>
+}
+
+impl AveragedWeights {
+    fn new(len: usize) -> Self {
+        Self { weights: vec![0.0; len], updates: vec![0.0; len] }
+    }
+
+    fn update(&mut self, index: usize, delta: f64, step: usize) {
+        self.weights[index] += delta;
+        self.updates[index] += step as f64 * delta;
+    }
+
+    fn averaged(self, steps: usize) -> Vec<f64> {
+        self.weights
+            .into_iter()
+            .zip(self.updates)
+            .map(|(w, u)| w - u / steps as f64)
+            .collect()
+    }
+}
+
+/// The averaged perceptron update, made for every training sentence that
+/// the current weights tag wrongly.
+#[derive(Debug)]
+pub struct PerceptronRule {
+    iterations: usize
+}
+
+impl Default for PerceptronRule {
+    fn default() -> Self {
+        Self { iterations: DEFAULT_ITERATIONS }
+    }
+}
+
+impl LearningRule for PerceptronRule {
+    fn token_features(&self, words: &[String], tokens: &[String], index: usize) -> Vec<String> {
+        token_features(words, tokens, index)
+    }
+}
+
+/// Trains a `PerceptronTagger` with the averaged perceptron.
+pub type PerceptronTrainer = LinearTrainer<PerceptronRule>;
+
+impl PerceptronTrainer {
+    /// Sets the number of passes made over the training sentences.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.rule_mut().iterations = iterations;
+        self
+    }
+
+    pub fn finalize(self) -> Result<PerceptronTagger, Box<dyn Error>> {
+        let (rule, TrainingSet { tag_set, feature_indices, examples, normalizer }) = self.into_training_set()?;
+
+        let num_tags = tag_set.len();
+        let mut weights = AveragedWeights::new(feature_indices.len() * num_tags);
+        let mut transitions = AveragedWeights::new((num_tags + 1) * num_tags);
+
+        let mut step = 1;
+        for _ in 0..rule.iterations {
+            for example in examples.iter() {
+                let predicted = viterbi(&weights.weights, &transitions.weights, num_tags, &example.features);
+
+                if predicted != example.tags {
+                    let mut gold_previous = num_tags;
+                    let mut predicted_previous = num_tags;
+                    for (time, features) in example.features.iter().enumerate() {
+                        let (gold, guess) = (example.tags[time], predicted[time]);
+                        if gold != guess {
+                            for &f in features {
+                                weights.update(f * num_tags + gold, 1.0, step);
+                                weights.update(f * num_tags + guess, -1.0, step);
+                            }
+                        }
+
+                        transitions.update(gold_previous * num_tags + gold, 1.0, step);
+                        transitions.update(predicted_previous * num_tags + guess, -1.0, step);
+                        gold_previous = gold;
+                        predicted_previous = guess;
+                    }
+                }
+
+                step += 1;
+            }
+        }
+
+        // Drop the features whose averaged weights never moved so that the
+        // saved model only keeps the ones that affect tagging.
+        let weights = weights.averaged(step);
+        let mut kept_indices = HashMap::new();
+        let mut kept_weights = Vec::new();
+        for (feature, index) in feature_indices {
+            let row = &weights[index * num_tags..(index + 1) * num_tags];
+            if row.iter().any(|&w| w != 0.0) {
+                kept_indices.insert(feature, kept_indices.len());
+                kept_weights.extend_from_slice(row);
+            }
+        }
+
+        Ok(PerceptronTagger::new(tag_set, kept_indices, kept_weights, transitions.averaged(step), normalizer))
+    }
+}
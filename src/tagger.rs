@@ -0,0 +1,24 @@
+use std::collections::HashMap;
+
+/// A model that assigns a tag to every token of a sentence.
+pub trait Tagger {
+    /// Tags `sentence`, returning one tag per token along with a score per
+    /// tag when the tagger can measure its confidence in them.
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>);
+
+    /// The posterior probability of every tag at each token, for taggers
+    /// that model one.
+    fn tag_posteriors(&self, _sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        None
+    }
+}
+
+impl<T: Tagger + ?Sized> Tagger for Box<T> {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        (**self).tag(sentence)
+    }
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        (**self).tag_posteriors(sentence)
+    }
+}
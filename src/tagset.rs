@@ -0,0 +1,168 @@
+use crate::nlp::TaggedSentence;
+use crate::Tagger;
+use std::collections::HashMap;
+use std::io::{BufReader, BufRead};
+use std::path::PathBuf;
+use std::error::Error;
+use std::fs::File;
+
+/// Penn Treebank tags and the universal tags (Petrov et al., 2012) they map
+/// to, including the bracket and quote spellings used by `data/penn-dev.txt`.
+const PENN_TO_UNIVERSAL: [(&str, &str); 53] = [
+    ("!", "."), ("#", "."), ("$", "."), ("''", "."), ("\"", "."), ("``", "."),
+    ("(", "."), (")", "."), ("-LRB-", "."), ("-RRB-", "."), ("LRB", "."), ("RRB", "."),
+    (",", "."), (".", "."), (":", "."), (";", "."), ("?", "."),
+    ("CC", "CONJ"), ("CD", "NUM"), ("DT", "DET"), ("EX", "DET"), ("FW", "X"),
+    ("IN", "ADP"), ("JJ", "ADJ"), ("JJR", "ADJ"), ("JJS", "ADJ"), ("LS", "X"),
+    ("MD", "VERB"), ("NN", "NOUN"), ("NNP", "NOUN"), ("NNPS", "NOUN"), ("NNS", "NOUN"),
+    ("PDT", "DET"), ("POS", "PRT"), ("PRP", "PRON"), ("PRP$", "PRON"), ("RB", "ADV"),
+    ("RBR", "ADV"), ("RBS", "ADV"), ("RP", "PRT"), ("SYM", "X"), ("TO", "PRT"),
+    ("UH", "X"), ("VB", "VERB"), ("VBD", "VERB"), ("VBG", "VERB"), ("VBN", "VERB"),
+    ("VBP", "VERB"), ("VBZ", "VERB"), ("WDT", "DET"), ("WP", "PRON"), ("WP$", "PRON"),
+    ("WRB", "ADV")
+];
+
+/// The coarse Brown tags used by `data/brown-dev.txt` and the universal
+/// tags they map to.
+const BROWN_TO_UNIVERSAL: [(&str, &str); 16] = [
+    ("NOUN", "NOUN"), ("VERB", "VERB"), ("MODAL", "VERB"), ("ADJ", "ADJ"),
+    ("ADV", "ADV"), ("PRON", "PRON"), ("DET", "DET"), ("IN", "ADP"),
+    ("NUM", "NUM"), ("CONJ", "CONJ"), ("TO", "PRT"), ("PART", "PRT"),
+    ("PERIOD", "."), ("PUNCT", "."), ("UH", "X"), ("X", "X")
+];
+
+/// Maps the tags of one tagset onto another. Tags without an entry are
+/// left unchanged, so the default mapping changes nothing.
+#[derive(Clone, Debug, Default)]
+pub struct TagMapping {
+    mapping: HashMap<String, String>
+}
+
+impl TagMapping {
+    pub fn penn_to_universal() -> Self {
+        Self::from_pairs(PENN_TO_UNIVERSAL)
+    }
+
+    pub fn brown_to_universal() -> Self {
+        Self::from_pairs(BROWN_TO_UNIVERSAL)
+    }
+
+    fn from_pairs(pairs: impl IntoIterator<Item=(&'static str, &'static str)>) -> Self {
+        let mapping = pairs
+            .into_iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+
+        Self { mapping }
+    }
+
+    /// Reads a mapping file with one whitespace-separated `FROM TO` pair per
+    /// line. Blank lines and lines starting with `#` are skipped.
+    pub fn from_file(path: PathBuf) -> Result<Self, Box<dyn Error>> {
+        let rdr = BufReader::new(File::open(path)?);
+        let mut mapping = HashMap::new();
+
+        for (number, line) in rdr.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [from, to] => mapping.insert(from.to_string(), to.to_string()),
+                _ => Err(format!("Expected a FROM TO pair on line {}", number + 1))?
+            };
+        }
+
+        Ok(Self { mapping })
+    }
+
+    /// Adds every entry of `other`, replacing any entries for the same tags.
+    pub fn extend(&mut self, other: TagMapping) {
+        self.mapping.extend(other.mapping);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mapping.is_empty()
+    }
+
+    pub fn map<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.mapping.get(tag).map_or(tag, String::as_str)
+    }
+
+    pub fn map_sentence(&self, sentence: TaggedSentence) -> TaggedSentence {
+        sentence
+            .into_iter()
+            .map(|(word, tag)| {
+                let tag = self.map(&tag).to_string();
+                (word, tag)
+            })
+            .collect()
+    }
+}
+
+/// A tagger whose tags are mapped onto another tagset. The posteriors of the
+/// tags that map to the same tag are summed, and each mapped tag is scored
+/// with its summed posterior. Scores of taggers without posteriors cannot
+/// be summed, so they are dropped unless the mapping is empty.
+pub struct MappedTagger<T> {
+    tagger: T,
+    mapping: TagMapping
+}
+
+impl<T: Tagger> MappedTagger<T> {
+    pub fn new(tagger: T, mapping: TagMapping) -> Self {
+        Self { tagger, mapping }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.tagger
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.tagger
+    }
+
+    pub fn mapping(&self) -> &TagMapping {
+        &self.mapping
+    }
+}
+
+impl<T: Tagger> Tagger for MappedTagger<T> {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        let (tags, scores) = self.tagger.tag(sentence);
+        let tags: Vec<String> = tags
+            .iter()
+            .map(|tag| self.mapping.map(tag).to_string())
+            .collect();
+
+        let scores = match scores {
+            Some(_) if !self.mapping.is_empty() => self.tag_posteriors(sentence).map(|posteriors| {
+                tags.iter()
+                    .zip(posteriors)
+                    .map(|(tag, marginals)| marginals.get(tag).copied().unwrap_or(0.0))
+                    .collect()
+            }),
+            scores => scores
+        };
+
+        (tags, scores)
+    }
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        let posteriors = self.tagger
+            .tag_posteriors(sentence)?
+            .into_iter()
+            .map(|marginals| {
+                let mut mapped: HashMap<String, f64> = HashMap::new();
+                for (tag, p) in marginals {
+                    *mapped.entry(self.mapping.map(&tag).to_string()).or_default() += p;
+                }
+                mapped
+            })
+            .collect();
+
+        Some(posteriors)
+    }
+}
@@ -1,8 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, hash_map};
 use super::StringCounter;
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct ConditionalStringCounter {
     counter: HashMap<String, StringCounter>
 }
@@ -16,29 +16,48 @@ impl ConditionalStringCounter {
 
     pub fn increment(&mut self, outer_key: &str, inner_key: &str) {
         self.counter.entry(outer_key.into())
-            .or_insert(StringCounter::new())
+            .or_default()
             .increment(inner_key);
     }
 
+    pub fn get(&self, outer_key: &str) -> Option<&StringCounter> {
+        self.counter.get(outer_key)
+    }
+
     pub fn extend(&mut self, other: ConditionalStringCounter) {
         for (tag, counter) in other.into_iter() {
             self.counter
                 .entry(tag)
-                .or_insert(StringCounter::new())
+                .or_default()
                 .extend(counter);
         }
     }
 
+    pub fn into_weights(self) -> HashMap<String, HashMap<String, f64>> {
+        self.counter.into_iter().map(|(key, counter)| (key, counter.into_weights())).collect()
+    }
+
     pub fn len(&self) -> usize {
         self.counter.len()
     }
 
-    pub fn iter(&self) -> impl Iterator<Item=(&String, &StringCounter)> {
-        self.counter.iter()
+    /// Adds every count of `other` to this counter, where `extend` replaces
+    /// the counts of the inner keys both share.
+    pub fn add_all(&mut self, other: ConditionalStringCounter) {
+        for (tag, counter) in other.into_iter() {
+            self.counter
+                .entry(tag)
+                .or_default()
+                .add_all(counter);
+        }
     }
 
-    pub fn into_iter(self) -> impl Iterator<Item=(String, StringCounter)> {
-        self.counter.into_iter()
+    pub fn is_empty(&self) -> bool {
+        self.counter.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&String, &StringCounter)> {
+        self.counter.iter()
     }
 
     pub fn keys(&self) -> impl Iterator<Item=&String> {
@@ -49,3 +68,12 @@ impl ConditionalStringCounter {
         self.counter.values()
     }
 }
+
+impl IntoIterator for ConditionalStringCounter {
+    type Item = (String, StringCounter);
+    type IntoIter = hash_map::IntoIter<String, StringCounter>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.counter.into_iter()
+    }
+}
@@ -1,10 +1,10 @@
-use super::{StringFrequencyDistribution, ConditionalStringCounter, ALPHA};
+use super::{StringFrequencyDistribution, ConditionalStringCounter, Smoothing};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, hash_map};
 
 pub(in crate::utils) const LIKELIHOOD_LOG_BASE: f64 = std::f64::consts::E;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ConditionalStringFrequencyDistribution {
     distribution: HashMap<String, StringFrequencyDistribution>
 }
@@ -18,17 +18,101 @@ impl ConditionalStringFrequencyDistribution {
         Self { distribution }
     }
 
-    pub fn with_conditional_smoothing(counter: ConditionalStringCounter, smoothing: StringFrequencyDistribution) -> Self {
-        let distribution = counter.into_iter()
-            .map(|(tag, counter)| {
-                let alpha = LIKELIHOOD_LOG_BASE.powf(smoothing.get_likelihood(&tag)) * ALPHA;
-                (tag, StringFrequencyDistribution::with_smoothing(counter, alpha))
+    pub fn from_default_weights(weights: impl IntoIterator<Item=(String, HashMap<String, f64>)>) -> Self {
+        let distribution = weights.into_iter()
+            .map(|(tag, w)| (tag, StringFrequencyDistribution::from_default_weights(w)))
+            .collect();
+
+        Self { distribution }
+    }
+
+    /// Smooths the possibly fractional counts of every outer key with
+    /// `smoothing`. The probability set aside for unseen inner keys is spread
+    /// over `backoff` if given, or else shared between the inner keys only
+    /// seen with other outer keys and one key never seen at all.
+    pub fn smoothed(
+        weights: impl IntoIterator<Item=(String, HashMap<String, f64>)>,
+        smoothing: Smoothing,
+        backoff: Option<&StringFrequencyDistribution>
+    ) -> Self {
+        let weights: Vec<(String, HashMap<String, f64>)> = weights.into_iter().collect();
+        let inner_keys: HashSet<&String> = weights.iter()
+            .flat_map(|(_, w)| w.iter().filter(|&(_, &w)| w > 0.0).map(|(key, _)| key))
+            .collect();
+        let num_inner_keys = inner_keys.len();
+
+        let distribution = weights.into_iter()
+            .map(|(key, weights)| {
+                let seen_keys = weights.values().filter(|&&w| w > 0.0).count();
+                let unseen_keys = num_inner_keys - seen_keys + 1;
+                (key, StringFrequencyDistribution::smoothed(weights, smoothing, unseen_keys, backoff))
             })
             .collect();
 
         Self { distribution }
     }
 
+    /// Smooths like `smoothed`, except that Lidstone smoothing scales α for
+    /// every outer key by the likelihood of that key under `scale`.
+    pub fn conditionally_smoothed(
+        weights: impl IntoIterator<Item=(String, HashMap<String, f64>)>,
+        smoothing: Smoothing,
+        scale: &StringFrequencyDistribution
+    ) -> Self {
+        let Smoothing::Lidstone(alpha) = smoothing else {
+            return Self::smoothed(weights, smoothing, None);
+        };
+
+        let distribution = weights.into_iter()
+            .map(|(tag, weights)| {
+                let alpha = LIKELIHOOD_LOG_BASE.powf(scale.get_likelihood(&tag)) * alpha;
+                (tag, StringFrequencyDistribution::from_weights(weights, alpha))
+            })
+            .collect();
+
+        Self { distribution }
+    }
+
+    /// Mixes the distributions conditioned on every outer key as
+    /// `weight * self + (1 - weight) * other`. Outer keys known to only one
+    /// side keep that side's distribution.
+    pub fn mix(&self, other: &Self, weight: f64) -> Self {
+        let smoothed_default = |d: &StringFrequencyDistribution, _: &str| d.smoothed_default();
+        self.mix_with(other, weight, smoothed_default, smoothed_default)
+    }
+
+    /// Mixes like `mix`, scoring the inner keys that only one side contains
+    /// with the given function of the other side's inner distribution.
+    pub fn mix_with(
+        &self,
+        other: &Self,
+        weight: f64,
+        self_unseen: impl Fn(&StringFrequencyDistribution, &str) -> f64,
+        other_unseen: impl Fn(&StringFrequencyDistribution, &str) -> f64
+    ) -> Self {
+        let mut distribution: HashMap<String, StringFrequencyDistribution> = self.distribution
+            .iter()
+            .map(|(key, d)| {
+                let mixed = match other.distribution.get(key) {
+                    Some(o) => d.mix_with(o, weight, &self_unseen, &other_unseen),
+                    None => d.clone()
+                };
+                (key.clone(), mixed)
+            })
+            .collect();
+        for (key, d) in other.distribution.iter() {
+            if !distribution.contains_key(key) {
+                distribution.insert(key.clone(), d.clone());
+            }
+        }
+
+        Self { distribution }
+    }
+
+    pub fn get(&self, outer_key: &str) -> Option<&StringFrequencyDistribution> {
+        self.distribution.get(outer_key)
+    }
+
     pub fn get_likelihood(&self, outer_key: &str, inner_key: &str) -> Option<f64> {
         self.distribution
             .get(outer_key)
@@ -45,10 +129,6 @@ impl ConditionalStringFrequencyDistribution {
         self.distribution.iter()
     }
 
-    pub fn into_iter(self) -> impl Iterator<Item=(String, StringFrequencyDistribution)> {
-        self.distribution.into_iter()
-    }
-
     pub fn keys(&self) -> impl Iterator<Item=&String> {
         self.distribution.keys()
     }
@@ -57,3 +137,12 @@ impl ConditionalStringFrequencyDistribution {
         self.distribution.values()
     }
 }
+
+impl IntoIterator for ConditionalStringFrequencyDistribution {
+    type Item = (String, StringFrequencyDistribution);
+    type IntoIter = hash_map::IntoIter<String, StringFrequencyDistribution>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.distribution.into_iter()
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::{HashMap, hash_map};
 use serde::{Deserialize, Serialize};
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub struct StringCounter {
     counter: HashMap<String, usize>
 }
@@ -14,20 +14,48 @@ impl StringCounter {
     }
 
     pub fn increment(&mut self, key: &str) {
+        self.increment_by(key, 1);
+    }
+
+    pub fn increment_by(&mut self, key: &str, amount: usize) {
         self.counter
             .entry(key.into())
-            .and_modify(|e| *e += 1)
-            .or_insert(1);
+            .and_modify(|e| *e += amount)
+            .or_insert(amount);
+    }
+
+    pub fn get(&self, key: &str) -> usize {
+        self.counter.get(key).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> usize {
+        self.counter.values().sum()
     }
 
     pub fn extend(&mut self, other: StringCounter) {
-        self.counter.extend(other.into_iter())
+        self.counter.extend(other)
+    }
+
+    /// Adds every count of `other` to this counter, where `extend` replaces
+    /// the counts of the keys both share.
+    pub fn add_all(&mut self, other: StringCounter) {
+        for (key, count) in other {
+            self.increment_by(&key, count);
+        }
+    }
+
+    pub fn into_weights(self) -> HashMap<String, f64> {
+        self.counter.into_iter().map(|(key, count)| (key, count as f64)).collect()
     }
 
     pub fn len(&self) -> usize {
         self.counter.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.counter.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item=(&String, &usize)> {
         self.counter.iter()
     }
@@ -1,10 +1,10 @@
 use std::collections::{HashMap, hash_map};
 use serde::{Deserialize, Serialize};
-use super::StringCounter;
+use super::{Smoothing, StringCounter};
 
 pub(in crate::utils) const ALPHA: f64 = 1e-5;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StringFrequencyDistribution {
     distribution: HashMap<String, f64>,
     smoothed_default: f64
@@ -16,26 +16,123 @@ impl StringFrequencyDistribution {
     }
 
     pub fn with_smoothing(counter: StringCounter, smoothing_scale: f64) -> Self {
-        let n: f64 = counter.values().sum::<usize>() as f64;
-        let v: f64 = counter.len() as f64;
+        Self::from_weights(counter.into_iter().map(|(key, count)| (key, count as f64)), smoothing_scale)
+    }
+
+    /// Builds a distribution from possibly fractional counts with
+    /// `smoothing`. The probability set aside for unseen keys is either spread
+    /// over the keys of `backoff` in proportion to their likelihoods there, or
+    /// shared evenly between `unseen_keys` keys. Without any counts, the
+    /// discounting strategies set aside all of it.
+    pub fn smoothed(
+        weights: impl IntoIterator<Item=(String, f64)>,
+        smoothing: Smoothing,
+        unseen_keys: usize,
+        backoff: Option<&Self>
+    ) -> Self {
+        let weights: Vec<(String, f64)> = weights.into_iter().filter(|&(_, w)| w > 0.0).collect();
+        let smoothing_scale = match smoothing {
+            Smoothing::Lidstone(alpha) => alpha,
+            _ => {
+                let (keys, counts): (Vec<String>, Vec<f64>) = weights.into_iter().unzip();
+                let (probabilities, unseen) = smoothing.discount(&counts);
+                // Keys discounted away entirely are left to the unseen keys.
+                let mut distribution: HashMap<String, f64> = keys
+                    .into_iter()
+                    .zip(probabilities)
+                    .filter(|&(_, p)| p > 0.0)
+                    .collect();
+
+                let smoothed_default = match backoff {
+                    Some(backoff) => {
+                        for (key, &likelihood) in backoff.iter() {
+                            *distribution.entry(key.clone()).or_default() += unseen * likelihood.exp();
+                        }
+                        unseen * backoff.smoothed_default.exp()
+                    },
+                    None => unseen / unseen_keys.max(1) as f64
+                };
+
+                return Self {
+                    distribution: distribution.into_iter().map(|(key, p)| (key, p.ln())).collect(),
+                    smoothed_default: smoothed_default.ln()
+                };
+            }
+        };
+
+        Self::from_weights(weights, smoothing_scale)
+    }
+
+    /// Builds a smoothed distribution from possibly fractional counts.
+    pub fn from_weights(weights: impl IntoIterator<Item=(String, f64)>, smoothing_scale: f64) -> Self {
+        let weights: Vec<(String, f64)> = weights.into_iter().collect();
+        let n: f64 = weights.iter().map(|(_, w)| w).sum();
+        let v: f64 = weights.len() as f64;
         let denominator = n + (smoothing_scale * (v + 1f64));
 
         let entry_to_prob = 
-            |(key, count): (String, usize)| (key, ((count as f64 + smoothing_scale) / denominator).ln());
+            |(key, weight): (String, f64)| (key, ((weight + smoothing_scale) / denominator).ln());
 
         let smoothed_default = (smoothing_scale / denominator).ln();
         Self {
-            distribution: HashMap::from_iter(counter.into_iter().map(entry_to_prob)),
+            distribution: HashMap::from_iter(weights.into_iter().map(entry_to_prob)),
             smoothed_default
         }
     }
 
+    pub fn from_default_weights(weights: impl IntoIterator<Item=(String, f64)>) -> Self {
+        Self::from_weights(weights, ALPHA)
+    }
+
+    /// Mixes two distributions as `weight * self + (1 - weight) * other`,
+    /// keeping every key of either one.
+    pub fn mix(&self, other: &Self, weight: f64) -> Self {
+        self.mix_with(other, weight, |d, _| d.smoothed_default, |d, _| d.smoothed_default)
+    }
+
+    /// Mixes two distributions like `mix`, scoring the keys that only one
+    /// side contains with the given function of the other side.
+    pub fn mix_with(
+        &self,
+        other: &Self,
+        weight: f64,
+        self_unseen: impl Fn(&Self, &str) -> f64,
+        other_unseen: impl Fn(&Self, &str) -> f64
+    ) -> Self {
+        let mix_likelihoods = |a: f64, b: f64| (weight * a.exp() + (1.0 - weight) * b.exp()).ln();
+
+        let mut distribution: HashMap<String, f64> = self.distribution
+            .iter()
+            .map(|(key, &likelihood)| {
+                let other_likelihood = match other.distribution.get(key) {
+                    Some(&l) => l,
+                    None => other_unseen(other, key)
+                };
+                (key.clone(), mix_likelihoods(likelihood, other_likelihood))
+            })
+            .collect();
+        for (key, &likelihood) in other.distribution.iter() {
+            if !distribution.contains_key(key) {
+                distribution.insert(key.clone(), mix_likelihoods(self_unseen(self, key), likelihood));
+            }
+        }
+
+        Self {
+            distribution,
+            smoothed_default: mix_likelihoods(self.smoothed_default, other.smoothed_default)
+        }
+    }
+
     pub fn get_likelihood(&self, key: &str) -> f64 {
         *self.distribution
             .get(key)
             .unwrap_or(&self.smoothed_default)
     }
 
+    pub fn smoothed_default(&self) -> f64 {
+        self.smoothed_default
+    }
+
     pub fn contains_key(&self, key: &str) -> bool {
         self.distribution.contains_key(key)
     }
@@ -2,8 +2,10 @@ mod counter;
 mod distribution;
 mod conditional_counter;
 mod conditional_distribution;
+mod smoothing;
 
 pub use counter::*;
 pub use distribution::*;
 pub use conditional_counter::*;
 pub use conditional_distribution::*;
+pub use smoothing::*;
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::{fmt, str::FromStr};
+use super::ALPHA;
+
+/// How a distribution estimated from counts sets aside probability for keys
+/// it has not seen.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Smoothing {
+    /// Adds α to every count, including that of a single unseen key. An α of
+    /// 1 is Laplace smoothing.
+    Lidstone(f64),
+    /// Sets aside as much probability for unseen keys as the share of
+    /// observations that were of a new key.
+    WittenBell,
+    /// Simple Good-Turing (Gale and Sampson, 1995), which re-estimates every
+    /// count from a log-linear fit of how many keys were seen that often.
+    GoodTuring,
+    /// Subtracts the discount from every count.
+    AbsoluteDiscounting(f64)
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::Lidstone(ALPHA)
+    }
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lidstone(alpha) => write!(f, "lidstone:{alpha}"),
+            Self::WittenBell => write!(f, "witten-bell"),
+            Self::GoodTuring => write!(f, "good-turing"),
+            Self::AbsoluteDiscounting(discount) => write!(f, "absolute:{discount}")
+        }
+    }
+}
+
+/// Parses `laplace`, `lidstone:ALPHA`, `witten-bell`, `good-turing` or
+/// `absolute:DISCOUNT`.
+impl FromStr for Smoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid smoothing {s}");
+        let parameter = |value: &str| value.parse::<f64>().map_err(|_| invalid());
+
+        let smoothing = match s.split_once(':') {
+            None if s == "laplace" => Self::Lidstone(1.0),
+            None if s == "witten-bell" => Self::WittenBell,
+            None if s == "good-turing" => Self::GoodTuring,
+            Some(("lidstone", alpha)) => Self::Lidstone(parameter(alpha)?),
+            Some(("absolute", discount)) => Self::AbsoluteDiscounting(parameter(discount)?),
+            _ => Err(invalid())?
+        };
+
+        match smoothing {
+            Self::Lidstone(alpha) if alpha <= 0.0 => Err(format!("The Lidstone α must be positive in {s}")),
+            Self::AbsoluteDiscounting(d) if !(d > 0.0 && d < 1.0) => {
+                Err(format!("The absolute discount must be between 0 and 1 in {s}"))
+            },
+            smoothing => Ok(smoothing)
+        }
+    }
+}
+
+impl Smoothing {
+    /// The discounted probability of every one of the positive, possibly
+    /// fractional, `counts` along with the total probability set aside for
+    /// unseen keys, which is all of it when there are no counts. Lidstone
+    /// smoothing is left to `StringFrequencyDistribution::from_weights`.
+    pub(in crate::utils) fn discount(&self, counts: &[f64]) -> (Vec<f64>, f64) {
+        if counts.is_empty() && !matches!(self, Self::Lidstone(_)) {
+            return (Vec::new(), 1.0);
+        }
+
+        let n: f64 = counts.iter().sum();
+        let t = counts.len() as f64;
+
+        match *self {
+            Self::Lidstone(_) => unreachable!("Lidstone smoothing does not discount counts"),
+            Self::WittenBell => {
+                let probabilities = counts.iter().map(|&c| c / (n + t)).collect();
+                (probabilities, t / (n + t))
+            },
+            Self::GoodTuring => good_turing(counts),
+            Self::AbsoluteDiscounting(discount) => {
+                let probabilities = counts.iter().map(|&c| (c - discount).max(0.0) / n).collect();
+                let freed: f64 = counts.iter().map(|&c| discount.min(c)).sum();
+                (probabilities, freed / n)
+            }
+        }
+    }
+}
+
+/// Simple Good-Turing smoothing: Turing's estimate `(r + 1) n(r + 1) / n(r)`
+/// of every count `r` is used until it is no longer significantly different
+/// from the one given by the log-linear fit of the frequencies of counts.
+/// Fractional counts are binned with the nearest whole count, at least 1, and
+/// scaled by the estimate of their bin.
+fn good_turing(counts: &[f64]) -> (Vec<f64>, f64) {
+    let bins: Vec<usize> = counts.iter().map(|&c| (c.round() as usize).max(1)).collect();
+    let mut frequencies: BTreeMap<usize, usize> = BTreeMap::new();
+    for &r in bins.iter() {
+        *frequencies.entry(r).or_default() += 1;
+    }
+
+    let n: f64 = counts.iter().sum();
+    let hapaxes = frequencies.get(&1).copied().unwrap_or(0).max(1);
+    // Keep some probability for the seen keys even when all were hapaxes.
+    let unseen = (hapaxes as f64 / n).min(0.5);
+
+    let rs: Vec<usize> = frequencies.keys().copied().collect();
+    let points: Vec<(f64, f64)> = rs
+        .iter()
+        .enumerate()
+        .map(|(i, &r)| {
+            let q = if i == 0 { 0 } else { rs[i - 1] };
+            let t = rs.get(i + 1).copied().unwrap_or(2 * r - q);
+            let z = 2.0 * frequencies[&r] as f64 / (t - q) as f64;
+            ((r as f64).ln(), z.ln())
+        })
+        .collect();
+
+    // A single count gives no slope to fit, so leave counts undiscounted.
+    let slope = if points.len() < 2 {
+        -1.0
+    } else {
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / points.len() as f64;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / points.len() as f64;
+        let covariance: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let variance: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+        covariance / variance
+    };
+
+    let mut use_fit = false;
+    let mut adjusted: HashMap<usize, f64> = HashMap::with_capacity(rs.len());
+    for &r in rs.iter() {
+        let rf = r as f64;
+        let fitted = (rf + 1.0) * (1.0 + 1.0 / rf).powf(slope);
+        let turing = frequencies.get(&(r + 1)).filter(|_| !use_fit).map(|&next| {
+            let (n_r, n_next) = (frequencies[&r] as f64, next as f64);
+            let estimate = (rf + 1.0) * n_next / n_r;
+            let deviation = 1.96 * ((rf + 1.0).powi(2) * n_next / n_r.powi(2) * (1.0 + n_next / n_r)).sqrt();
+            (estimate, deviation)
+        });
+
+        let r_star = match turing {
+            Some((estimate, deviation)) if (estimate - fitted).abs() > deviation => estimate,
+            _ => {
+                use_fit = true;
+                fitted
+            }
+        };
+        adjusted.insert(r, r_star / rf);
+    }
+
+    let scaled: Vec<f64> = counts.iter().zip(bins).map(|(c, r)| c * adjusted[&r]).collect();
+    let total: f64 = scaled.iter().sum();
+    let probabilities = scaled.into_iter().map(|c| (1.0 - unseen) * c / total).collect();
+
+    (probabilities, unseen)
+}
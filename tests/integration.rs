@@ -1,6 +1,51 @@
 use pos_tagger::hmm::*;
+use pos_tagger::nlp::{extract_word_and_tag, CaseFolding, Normalizer, TaggedSentence};
+use pos_tagger::{StringCounter, Tagger};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
 use std::error::Error;
 
+/// A path under the temporary directory that is unique to this test run and
+/// removed once dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        Self(std::env::temp_dir().join(format!("pos-tagger-{}-{}-{}", std::process::id(), count, name)))
+    }
+
+    fn path(&self) -> PathBuf {
+        self.0.clone()
+    }
+}
+
+impl AsRef<Path> for TempFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Parses sentences of `WORD=TAG` pairs written out in a test.
+fn tagged_sentences(sentences: &[&str]) -> Vec<TaggedSentence> {
+    sentences.iter().map(|s| extract_word_and_tag(s)).collect()
+}
+
+/// Saves `model` and loads it back.
+fn reload(model: &POSTaggingHMM) -> Result<POSTaggingHMM, Box<dyn Error>> {
+    let file = TempFile::new("model.hmm");
+    model.save(file.path())?;
+    POSTaggingHMM::from_file(file.path())
+}
+
 #[test]
 fn test_it_all_works() -> Result<(), Box<dyn Error>> {
     let model = POSTaggingHMMTrainer::new()
@@ -20,3 +65,1065 @@ fn test_training_works() -> Result<(), Box<dyn Error>> {
     
     Ok(())
 }
+
+#[test]
+fn test_training_skips_empty_sentences() -> Result<(), Box<dyn Error>> {
+    // The empty sentence adds no counts, so the checkpoints are identical.
+    let empty = TempFile::new("empty-sentence.ckpt");
+    let nothing = TempFile::new("no-sentences.ckpt");
+    POSTaggingHMMTrainer::new().train_sentences(vec![vec![]]).save(empty.path())?;
+    POSTaggingHMMTrainer::new().train_sentences(Vec::new()).save(nothing.path())?;
+    assert_eq!(std::fs::read(&empty)?, std::fs::read(&nothing)?);
+
+    let model = POSTaggingHMMTrainer::new()
+        .train_sentences(vec![vec![]])
+        .train_sentences(tagged_sentences(&["a=A b=B"]))
+        .finalize()?;
+    assert_eq!(model.predict(vec!["a".into(), "b".into()]).len(), 2);
+
+    // Neither do blank lines of a data file.
+    let data = TempFile::new("blank-lines.txt");
+    std::fs::write(&data, "a=A b=B\n\nb=B a=A\n\n")?;
+    let from_file = POSTaggingHMMTrainer::new().train(data.path())?.finalize()?;
+    let from_sentences = POSTaggingHMMTrainer::new()
+        .train_sentences(tagged_sentences(&["a=A b=B", "b=B a=A"]))
+        .finalize()?;
+    let scores = |model: &POSTaggingHMM| -> Vec<f64> {
+        model.predict_n_best(vec!["a".into(), "b".into()], 4).into_iter().map(|(score, _)| score).collect()
+    };
+    assert_eq!(scores(&from_file), scores(&from_sentences));
+
+    Ok(())
+}
+
+#[test]
+fn test_second_order_training_works() -> Result<(), Box<dyn Error>> {
+    let model = POSTaggingHMMTrainer::new()
+        .with_order(HMMOrder::Second)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+
+    assert_eq!(model.order(), HMMOrder::Second);
+    let weights = model.interpolation_weights().unwrap();
+    assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+    let sentence = vec!["cat".to_string(), "dog".to_string(), "cow".to_string()];
+    let prediction = model.predict(sentence.clone());
+    let words: Vec<String> = prediction.into_iter().map(|(w, _)| w).collect();
+    assert_eq!(words, sentence);
+
+    Ok(())
+}
+
+#[test]
+fn test_first_word_is_scored_as_written() -> Result<(), Box<dyn Error>> {
+    let file = TempFile::new("first-word.txt");
+    std::fs::write(&file, [
+        "the=D cats=N were=V running=G .=.",
+        "the=D dogs=N were=V walking=G .=.",
+        "the=D birds=N were=V singing=G .=.",
+        "the=D horses=N were=V eating=G .=."
+    ].join("\n"))?;
+    let model = POSTaggingHMMTrainer::new()
+        .train(file.path())?
+        .finalize()?;
+
+    let best_score = |words: [&str; 2]| -> f64 {
+        model.predict_n_best(words.map(String::from).to_vec(), 1)[0].0
+    };
+    assert_eq!(best_score(["jumping", "were"]), best_score(["zebras", "were"]));
+    assert_ne!(best_score(["were", "jumping"]), best_score(["were", "zebras"]));
+
+    Ok(())
+}
+
+#[test]
+fn test_n_best_is_ranked_and_led_by_viterbi() -> Result<(), Box<dyn Error>> {
+    for order in [HMMOrder::First, HMMOrder::Second] {
+        let model = POSTaggingHMMTrainer::new()
+            .with_order(order)
+            .train("tests/data/mttest-training.txt".into())?
+            .finalize()?;
+
+        let sentence = vec!["cow".to_string(), "cat".to_string(), "dog".to_string()];
+        let n_best = model.predict_n_best(sentence.clone(), 5);
+
+        assert_eq!(n_best.len(), 5);
+        assert_eq!(n_best[0].1, model.predict(sentence));
+        assert!(n_best.windows(2).all(|w| w[0].0 >= w[1].0));
+        for (i, (_, a)) in n_best.iter().enumerate() {
+            assert!(n_best[i + 1..].iter().all(|(_, b)| a != b));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_posteriors_are_distributions() -> Result<(), Box<dyn Error>> {
+    for order in [HMMOrder::First, HMMOrder::Second] {
+        let model = POSTaggingHMMTrainer::new()
+            .with_order(order)
+            .train("tests/data/mttest-training.txt".into())?
+            .finalize()?;
+
+        let sentence = vec!["cow".to_string(), "cat".to_string(), "dog".to_string()];
+        let posteriors = model.posteriors(sentence.clone());
+        assert_eq!(posteriors.len(), sentence.len());
+
+        for ((word, marginals), expected) in posteriors.iter().zip(&sentence) {
+            assert_eq!(word, expected);
+            assert_eq!(marginals.len(), model.tag_set().len());
+            assert!((marginals.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+
+        let first_tag = model.tag_set()
+            .iter()
+            .zip(&posteriors[0].1)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(t, _)| t.clone());
+        assert_eq!(first_tag.as_deref(), Some("A"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_posterior_decoding_tags_every_word() -> Result<(), Box<dyn Error>> {
+    let mut model = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    model.set_decoding_strategy(DecodingStrategy::Posterior);
+
+    let sentence = vec!["cow".to_string(), "cat".to_string(), "dog".to_string()];
+    let prediction = model.predict(sentence.clone());
+
+    assert_eq!(prediction.len(), sentence.len());
+    assert_eq!(prediction[0].1, "A");
+    Ok(())
+}
+
+#[test]
+fn test_reloaded_model_predicts_identically() -> Result<(), Box<dyn Error>> {
+    let model = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+
+    let reloaded = reload(&model)?;
+
+    for sentence in [vec!["cow", "cat", "dog"], vec!["pit", "unknown", "words"]] {
+        let sentence: Vec<String> = sentence.into_iter().map(String::from).collect();
+        assert_eq!(model.predict(sentence.clone()), reloaded.predict(sentence));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_wide_beam_matches_viterbi() -> Result<(), Box<dyn Error>> {
+    for order in [HMMOrder::First, HMMOrder::Second] {
+        let mut model = POSTaggingHMMTrainer::new()
+            .with_order(order)
+            .train("tests/data/mttest-training.txt".into())?
+            .finalize()?;
+
+        let sentence = vec!["cow".to_string(), "pat".to_string(), "hawk".to_string()];
+        let exact = model.predict(sentence.clone());
+
+        model.set_decoding_strategy(DecodingStrategy::Beam { width: usize::MAX, threshold: None });
+        assert_eq!(model.predict(sentence.clone()), exact);
+
+        model.set_decoding_strategy(DecodingStrategy::Beam { width: 1, threshold: Some(0.0) });
+        let greedy = model.predict(sentence.clone());
+        assert_eq!(greedy.len(), sentence.len());
+
+        model.set_decoding_strategy(DecodingStrategy::Beam { width: usize::MAX, threshold: Some(-1.0) });
+        assert_eq!(model.predict(sentence.clone()), greedy);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_constrained_prediction_respects_pinned_tags() -> Result<(), Box<dyn Error>> {
+    for order in [HMMOrder::First, HMMOrder::Second] {
+        let model = POSTaggingHMMTrainer::new()
+            .with_order(order)
+            .train("tests/data/mttest-training.txt".into())?
+            .finalize()?;
+
+        let sentence = vec!["cow".to_string(), "cat".to_string(), "dog".to_string()];
+        let constraints = vec![
+            Some(TagConstraint::Fixed("C".into())),
+            Some(TagConstraint::OneOf(vec!["A".into(), "B".into()])),
+            None
+        ];
+
+        let prediction = model.predict_constrained(sentence.clone(), &constraints)?;
+        assert_eq!(prediction.len(), sentence.len());
+        assert_eq!(prediction[0].1, "C");
+        assert!(prediction[1].1 == "A" || prediction[1].1 == "B");
+
+        let unconstrained = model.predict_constrained(sentence.clone(), &[None, None, None])?;
+        assert_eq!(unconstrained, model.predict(sentence.clone()));
+
+        let unknown = vec![Some(TagConstraint::Fixed("Z".into())), None, None];
+        assert!(model.predict_constrained(sentence.clone(), &unknown).is_err());
+        assert!(model.predict_constrained(sentence, &[None]).is_err());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_dictionary_restricts_known_words() -> Result<(), Box<dyn Error>> {
+    let model = POSTaggingHMMTrainer::new()
+        .with_tag_dictionary_cutoff(1)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+
+    assert!(model.tag_dictionary_len() > 0);
+
+    let sentence = vec!["cow".to_string(), "pit".to_string(), "dog".to_string()];
+    assert_eq!(model.predict(sentence.clone())[1].1, "A");
+
+    let (_, marginals) = &model.posteriors(sentence.clone())[1];
+    let a = model.tag_set().iter().position(|t| t == "A").unwrap();
+    assert!((marginals[a] - 1.0).abs() < 1e-9);
+
+    for tag in ["B", "C"] {
+        let pinned = vec![None, Some(TagConstraint::Fixed(tag.into())), None];
+        assert_eq!(model.predict_constrained(sentence.clone(), &pinned)?[1].1, tag);
+    }
+
+    let rare = POSTaggingHMMTrainer::new()
+        .with_tag_dictionary_cutoff(100)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    assert_eq!(rare.tag_dictionary_len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_baum_welch_increases_likelihood() -> Result<(), Box<dyn Error>> {
+    let trainer = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?;
+
+    let mut log_likelihoods = Vec::new();
+    let model = BaumWelchTrainer::from_trainer(trainer)?
+        .train("tests/data/mttest-untagged.txt".into())?
+        .run(5, 0.0, |_, ll| log_likelihoods.push(ll));
+
+    assert_eq!(log_likelihoods.len(), 5);
+    assert!(log_likelihoods.windows(2).all(|w| w[1] >= w[0] - 1e-6));
+    assert_eq!(model.predict(vec!["cat".into(), "dog".into()]).len(), 2);
+
+    let second_order = POSTaggingHMMTrainer::new()
+        .with_order(HMMOrder::Second)
+        .train("tests/data/mttest-training.txt".into())?;
+    assert!(BaumWelchTrainer::from_trainer(second_order).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_baum_welch_keeps_the_smoothing_of_the_model() -> Result<(), Box<dyn Error>> {
+    let sentences = ["x=A a=B b=A", "a=B b=A a=B", "b=A a=B b=A a=B"];
+    let file = TempFile::new("untagged.txt");
+    std::fs::write(&file, "x a b\na b a\nb a b a\n")?;
+
+    let model = POSTaggingHMMTrainer::new()
+        .with_kept_counts(true)
+        .train_sentences(tagged_sentences(&sentences))
+        .finalize()?;
+    let mut reestimated = BaumWelchTrainer::new(reload(&model)?)?
+        .train(file.path())?
+        .run(1, 0.0, |_, _| ());
+
+    // Every sentence is tagged without ambiguity, so the expected counts are
+    // the counts the model was trained on.
+    for sentence in ["x a b", "a b a b", "b z"] {
+        let sentence: Vec<String> = sentence.split(' ').map(String::from).collect();
+        let (score, tags) = model.predict_with_score(sentence.clone());
+        let (reestimated_score, reestimated_tags) = reestimated.predict_with_score(sentence);
+        assert_eq!(tags, reestimated_tags);
+        assert!((score - reestimated_score).abs() < 1e-3);
+    }
+
+    // The counts no longer describe the re-estimated model.
+    assert!(!reestimated.has_counts());
+    assert!(reestimated.update(tagged_sentences(&["a=B b=A"])).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_self_training_keeps_confident_sentences() -> Result<(), Box<dyn Error>> {
+    let trainer = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?;
+
+    let mut kept = Vec::new();
+    let model = SelfTrainer::new(trainer.clone())
+        .train("tests/data/mttest-untagged.txt".into())?
+        .run(2, f64::NEG_INFINITY, |_, k, total| kept.push((k, total)))?;
+
+    assert_eq!(kept, vec![(5, 5), (5, 5)]);
+    assert_eq!(model.predict(vec!["cat".into(), "dog".into()]).len(), 2);
+
+    let mut kept = Vec::new();
+    SelfTrainer::new(trainer)
+        .train("tests/data/mttest-untagged.txt".into())?
+        .run(1, 0.0, |_, k, _| kept.push(k))?;
+    assert_eq!(kept, vec![0]);
+
+    Ok(())
+}
+
+struct ConstantTagger(String);
+
+impl Tagger for ConstantTagger {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        (vec![self.0.clone(); sentence.len()], None)
+    }
+}
+
+/// Always predicts a single tag, whatever the length of the sentence.
+struct OneTagTagger;
+
+impl Tagger for OneTagTagger {
+    fn tag(&self, _: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        (vec!["A".into()], None)
+    }
+}
+
+/// Gives every token the same posteriors.
+struct ConstantPosteriors(Vec<(&'static str, f64)>);
+
+impl Tagger for ConstantPosteriors {
+    fn tag(&self, sentence: &[String]) -> (Vec<String>, Option<Vec<f64>>) {
+        let (tag, _) = self.0.iter().max_by(|(_, p1), (_, p2)| p1.total_cmp(p2)).unwrap();
+        (vec![tag.to_string(); sentence.len()], None)
+    }
+
+    fn tag_posteriors(&self, sentence: &[String]) -> Option<Vec<HashMap<String, f64>>> {
+        let posteriors = self.0.iter().map(|&(tag, p)| (tag.to_string(), p)).collect();
+        Some(vec![posteriors; sentence.len()])
+    }
+}
+
+#[test]
+fn test_evaluation_is_generic_over_taggers() -> Result<(), Box<dyn Error>> {
+    let mut model = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+
+    let sentence: Vec<String> = vec!["cat".into(), "cat".into(), "dog".into()];
+    let (tags, scores) = model.tag(&sentence);
+    let predicted: Vec<String> = model.predict(sentence.clone()).into_iter().map(|(_, t)| t).collect();
+    assert_eq!(tags, predicted);
+    assert!(scores.is_none());
+
+    model.set_decoding_strategy(DecodingStrategy::Posterior);
+    let (tags, scores) = model.tag(&sentence);
+    assert_eq!(tags.len(), sentence.len());
+    assert!(scores.unwrap().iter().all(|&p| p > 0.0 && p <= 1.0 + 1e-9));
+
+    let accuracy = evaluate(&ConstantTagger("Z".into()), "tests/data/mttest-dev.txt".into())?;
+    assert_eq!(accuracy, 0.0);
+    assert_eq!(par_evaluate(&model, "tests/data/mttest-dev.txt".into())?, evaluate(&model, "tests/data/mttest-dev.txt".into())?);
+    assert!(evaluate(&OneTagTagger, "tests/data/mttest-dev.txt".into()).is_err());
+    assert!(par_evaluate(&OneTagTagger, "tests/data/mttest-dev.txt".into()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_perceptron_learns_and_reloads() -> Result<(), Box<dyn Error>> {
+    let model = pos_tagger::perceptron::PerceptronTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+
+    let accuracy = evaluate(&model, "tests/data/mttest-training.txt".into())?;
+    assert!(accuracy > 50.0);
+
+    let path = std::env::temp_dir().join("pos-tagger-perceptron-test.bin");
+    model.save(path.clone())?;
+    let reloaded = pos_tagger::perceptron::PerceptronTagger::from_file(path.clone())?;
+    assert!(POSTaggingHMM::from_file(path.clone()).is_err());
+    std::fs::remove_file(path)?;
+
+    let sentence: Vec<String> = vec!["cat".into(), "dog".into(), "cat".into()];
+    assert_eq!(model.predict(sentence.clone()), reloaded.predict(sentence));
+
+    Ok(())
+}
+
+/// Sentences in which only capitalization tells the tags of names apart.
+const CAPITALIZED_NAMES: [&str; 6] = [
+    "the=DT Paris=NNP sleeps=VBZ",
+    "the=DT cat=NN sleeps=VBZ",
+    "the=DT London=NNP sleeps=VBZ",
+    "the=DT dog=NN sleeps=VBZ",
+    "the=DT Madrid=NNP sleeps=VBZ",
+    "the=DT bird=NN sleeps=VBZ"
+];
+
+#[test]
+fn test_perceptron_spelling_features_keep_capitalization() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::perceptron::*;
+
+    let model = PerceptronTrainer::new()
+        .train_sentences(tagged_sentences(&CAPITALIZED_NAMES))
+        .finalize()?;
+    let tags = |model: &PerceptronTagger, word: &str| model.tag(&["the".into(), word.into(), "sleeps".into()]).0;
+    assert_eq!(tags(&model, "Rome")[1], "NNP");
+    assert_eq!(tags(&model, "rome")[1], "NN");
+    assert_eq!(model.predict(vec!["Rome".into()])[0].0, "Rome");
+
+    Ok(())
+}
+
+#[test]
+fn test_crf_learns_and_reloads() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::crf::*;
+
+    let templates: Vec<FeatureTemplate> = DEFAULT_FEATURE_TEMPLATES
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<_, _>>()?;
+    let printed: Vec<String> = templates.iter().map(ToString::to_string).collect();
+    assert_eq!(printed.join(","), DEFAULT_FEATURE_TEMPLATES);
+    assert!("suf0[0]".parse::<FeatureTemplate>().is_err());
+    assert!("w[x]".parse::<FeatureTemplate>().is_err());
+
+    let mut log_likelihoods = Vec::new();
+    let model = CRFTrainer::new()
+        .with_templates(templates)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize_with_progress(|_, ll| log_likelihoods.push(ll))?;
+    assert!(log_likelihoods.last() > log_likelihoods.first());
+    assert!(evaluate(&model, "tests/data/mttest-training.txt".into())? > 50.0);
+
+    let sentence: Vec<String> = vec!["cat".into(), "dog".into(), "cat".into()];
+    for (_, posteriors) in model.posteriors(sentence.clone()) {
+        assert!((posteriors.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+    assert!(model.posteriors(Vec::new()).is_empty());
+    assert_eq!(model.tag_posteriors(&[]), Some(Vec::new()));
+    assert_eq!(model.tag(&[]), (Vec::new(), Some(Vec::new())));
+
+    let overdecayed = CRFTrainer::new()
+        .with_learning_rate(1.0)
+        .with_l2(1e6)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize();
+    assert!(overdecayed.is_err());
+    assert!(CRFTrainer::new().with_learning_rate(f64::NAN).train_sentences(tagged_sentences(&["a=A"])).finalize().is_err());
+
+    let path = std::env::temp_dir().join("pos-tagger-crf-test.bin");
+    model.save(path.clone())?;
+    let reloaded = CRFTagger::from_file(path.clone())?;
+    std::fs::remove_file(path)?;
+    assert_eq!(model.predict(sentence.clone()), reloaded.predict(sentence));
+
+    let model = CRFTrainer::new().train_sentences(tagged_sentences(&CAPITALIZED_NAMES)).finalize()?;
+    let tags = |model: &CRFTagger, word: &str| model.tag(&["the".into(), word.into(), "sleeps".into()]).0;
+    assert_eq!(tags(&model, "Rome")[1], "NNP");
+    assert_eq!(tags(&model, "rome")[1], "NN");
+    assert_eq!(model.posteriors(vec!["Rome".into()])[0].0, "Rome");
+
+    Ok(())
+}
+
+#[test]
+fn test_ensembles_combine_members() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::ensemble::*;
+
+    let train = || -> Result<POSTaggingHMM, Box<dyn Error>> {
+        POSTaggingHMMTrainer::new()
+            .train("tests/data/mttest-training.txt".into())?
+            .finalize()
+    };
+    let sentence: Vec<String> = vec!["cat".into(), "dog".into(), "cat".into()];
+    let (expected, _) = train()?.tag(&sentence);
+
+    for vote in [Vote::Majority, Vote::LogPosterior] {
+        let ensemble = Ensemble::new(vec![train()?, train()?], vote)?;
+        assert_eq!(ensemble.tag(&sentence).0, expected);
+    }
+
+    let constant = || -> Box<dyn Tagger> { Box::new(ConstantTagger("Z".into())) };
+    let members: Vec<Box<dyn Tagger>> = vec![Box::new(train()?), constant(), constant()];
+    let (tags, scores) = Ensemble::new(members, Vote::Majority)?.tag(&sentence);
+    assert!(tags.iter().all(|t| t == "Z"));
+    assert!(scores.unwrap().iter().all(|&s| (s - 2.0 / 3.0).abs() < 1e-9));
+
+    let members: Vec<Box<dyn Tagger>> = vec![Box::new(train()?), constant(), constant()];
+    let ensemble = Ensemble::new(members, Vote::Weighted(vec![3.0, 1.0, 1.0]))?;
+    assert_eq!(ensemble.tag(&sentence).0, expected);
+
+    // Members without posteriors cannot veto the tags of those with them.
+    let members: Vec<Box<dyn Tagger>> = vec![constant(), Box::new(train()?)];
+    assert_eq!(Ensemble::new(members, Vote::LogPosterior)?.tag(&sentence).0, expected);
+    let (tags, _) = Ensemble::new(vec![constant(), constant()], Vote::LogPosterior)?.tag(&sentence);
+    assert!(tags.iter().all(|t| t == "Z"));
+
+    let prefers_y = || -> Box<dyn Tagger> { Box::new(ConstantPosteriors(vec![("X", 0.4), ("Y", 0.6)])) };
+    let prefers_x = || -> Box<dyn Tagger> { Box::new(ConstantPosteriors(vec![("X", 0.6), ("Y", 0.4)])) };
+    let (tags, _) = Ensemble::new(vec![prefers_y(), prefers_x()], Vote::LogPosterior)?.tag(&sentence);
+    assert!(tags.iter().all(|t| t == "Y"));
+    let (tags, _) = Ensemble::new(vec![prefers_x(), prefers_y()], Vote::LogPosterior)?.tag(&sentence);
+    assert!(tags.iter().all(|t| t == "X"));
+
+    // A member that drops tokens fails the ensemble instead of panicking.
+    let members: Vec<Box<dyn Tagger>> = vec![Box::new(train()?), Box::new(OneTagTagger)];
+    let ensemble = Ensemble::new(members, Vote::Majority)?;
+    assert!(ensemble.try_tag(&sentence).is_err());
+    assert!(evaluate(&ensemble, "tests/data/mttest-dev.txt".into()).is_err());
+
+    assert!(Ensemble::new(vec![train()?], Vote::Weighted(vec![1.0, 2.0])).is_err());
+    assert!(Ensemble::new(vec![train()?, train()?], Vote::Weighted(vec![1.0, -2.0])).is_err());
+    assert!(Ensemble::new(vec![train()?, train()?], Vote::Weighted(vec![1.0, f64::NAN])).is_err());
+    assert!(Ensemble::<POSTaggingHMM>::new(Vec::new(), Vote::Majority).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_counters_extend_replaces_and_add_all_adds() {
+    let counter = |counts: &[(&str, usize)]| {
+        let mut counter = StringCounter::new();
+        counts.iter().for_each(|&(key, count)| counter.increment_by(key, count));
+        counter
+    };
+
+    let mut extended = counter(&[("a", 1), ("b", 2)]);
+    extended.extend(counter(&[("b", 5), ("c", 1)]));
+    assert_eq!((extended.get("a"), extended.get("b"), extended.get("c")), (1, 5, 1));
+
+    let mut added = counter(&[("a", 1), ("b", 2)]);
+    added.add_all(counter(&[("b", 5), ("c", 1)]));
+    assert_eq!((added.get("a"), added.get("b"), added.get("c")), (1, 7, 1));
+}
+
+#[test]
+fn test_checkpoints_resume_and_merge() -> Result<(), Box<dyn Error>> {
+    let data: PathBuf = "tests/data/mttest-training.txt".into();
+    let file = TempFile::new("checkpoint.bin");
+    POSTaggingHMMTrainer::new().train(data.clone())?.save(file.path())?;
+
+    let resumed = POSTaggingHMMTrainer::from_file(file.path())?;
+    assert!(POSTaggingHMM::from_file(file.path()).is_err());
+
+    let merged = POSTaggingHMMTrainer::new()
+        .train(data.clone())?
+        .merge(resumed)?
+        .finalize()?;
+    let doubled = POSTaggingHMMTrainer::new()
+        .train(data.clone())?
+        .train(data)?
+        .finalize()?;
+
+    let sentence: Vec<String> = vec!["cat".into(), "dog".into(), "cat".into()];
+    let merged = merged.tag_posteriors(&sentence).unwrap();
+    let doubled = doubled.tag_posteriors(&sentence).unwrap();
+    for (m, d) in merged.iter().zip(doubled.iter()) {
+        assert!(m.iter().all(|(tag, p)| (p - d[tag]).abs() < 1e-9));
+    }
+
+    let trigram = POSTaggingHMMTrainer::new().with_order(HMMOrder::Second);
+    assert!(trigram.merge(POSTaggingHMMTrainer::new()).is_err());
+    let case_sensitive = POSTaggingHMMTrainer::new().with_case_sensitivity(true);
+    assert!(case_sensitive.merge(POSTaggingHMMTrainer::new()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_update_matches_training_on_all_sentences() -> Result<(), Box<dyn Error>> {
+    let data: PathBuf = "tests/data/mttest-training.txt".into();
+    let extra: Vec<TaggedSentence> = vec![
+        vec![("Dog".into(), "C".into()), ("cat".into(), "B".into())],
+        vec![("dog".into(), "A".into())],
+        vec![]
+    ];
+
+    let mut updated = reload(&POSTaggingHMMTrainer::new().with_kept_counts(true).train(data.clone())?.finalize()?)?;
+    updated.update(extra.clone())?;
+    assert!(updated.has_counts());
+
+    let retrained = POSTaggingHMMTrainer::new()
+        .train(data)?
+        .train_sentences(extra)
+        .finalize()?;
+    assert!(!retrained.has_counts());
+
+    let sentence: Vec<String> = vec!["dog".into(), "cat".into(), "cat".into()];
+    let updated_posteriors = updated.tag_posteriors(&sentence).unwrap();
+    let retrained_posteriors = retrained.tag_posteriors(&sentence).unwrap();
+    for (u, r) in updated_posteriors.iter().zip(retrained_posteriors.iter()) {
+        assert!(u.iter().all(|(tag, p)| (p - r[tag]).abs() < 1e-9));
+    }
+
+    let mut uncounted = reload(&retrained)?;
+    assert!(!uncounted.has_counts());
+    assert!(uncounted.update(Vec::new()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_mixing_a_model_with_itself_changes_nothing() -> Result<(), Box<dyn Error>> {
+    let model = POSTaggingHMMTrainer::new()
+        .with_tag_dictionary_cutoff(2)
+        .with_kept_counts(true)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    let mut mixed = model.mix(&model, 0.3)?;
+    assert_eq!(mixed.tag_dictionary_len(), model.tag_dictionary_len());
+    assert!(!mixed.has_counts());
+    assert!(mixed.update(Vec::new()).is_err());
+
+    let sentence: Vec<String> = vec!["cow".into(), "cat".into(), "unknown".into()];
+    let expected = model.tag_posteriors(&sentence).unwrap();
+    let actual = mixed.tag_posteriors(&sentence).unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!(a.iter().all(|(tag, p)| (p - e[tag]).abs() < 1e-9));
+    }
+
+    let mut weights = Vec::new();
+    let mut accuracies = Vec::new();
+    let (weight, tuned) = tune_mixture_weight(&model, &model, "tests/data/mttest-dev.txt".into(), 4, &Default::default(), |w, a| {
+        weights.push(w);
+        accuracies.push(a);
+    })?;
+    assert_eq!(weights, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+    assert!(weights.contains(&weight));
+    let accuracy = evaluate(&model, "tests/data/mttest-dev.txt".into())?;
+    assert!(accuracies.iter().all(|a| (a - accuracy).abs() < 1e-9));
+    assert_eq!(evaluate(&tuned, "tests/data/mttest-dev.txt".into())?, accuracy);
+
+    let file = TempFile::new("mix-unknown-tags.txt");
+    std::fs::write(&file, "A X\nB X\nC X\nD X\n")?;
+    let unknown_tags = pos_tagger::tagset::TagMapping::from_file(file.path())?;
+    accuracies.clear();
+    tune_mixture_weight(&model, &model, "tests/data/mttest-dev.txt".into(), 4, &unknown_tags, |_, a| accuracies.push(a))?;
+    assert!(accuracies.iter().all(|&a| a == 0.0));
+
+    assert!(model.mix(&model, 1.5).is_err());
+    let second_order = POSTaggingHMMTrainer::new()
+        .with_order(HMMOrder::Second)
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    assert!(model.mix(&second_order, 0.5).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_tag_mappings_apply_to_models_and_data() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::tagset::{MappedTagger, TagMapping};
+    use pos_tagger::ensemble::{Ensemble, Vote};
+
+    let penn = TagMapping::penn_to_universal();
+    assert_eq!(penn.map("VBZ"), "VERB");
+    assert_eq!(penn.map("PRP$"), "PRON");
+    assert_eq!(TagMapping::brown_to_universal().map("PERIOD"), ".");
+    assert_eq!(penn.map("NOT-A-TAG"), "NOT-A-TAG");
+
+    let path = std::env::temp_dir().join("pos-tagger-mapping-test.txt");
+    std::fs::write(&path, "# merge B and C\nB BC\nC BC\n\n")?;
+    let mapping = TagMapping::from_file(path.clone())?;
+    std::fs::write(&path, "A\n")?;
+    assert!(TagMapping::from_file(path.clone()).is_err());
+    std::fs::remove_file(path)?;
+
+    let model = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    let sentence: Vec<String> = vec!["cow".into(), "cat".into(), "dog".into()];
+    let posteriors = model.tag_posteriors(&sentence).unwrap();
+
+    let mut mapped = MappedTagger::new(model, mapping.clone());
+    assert!(mapped.tag(&sentence).0.iter().all(|tag| tag != "B" && tag != "C"));
+    for (m, p) in mapped.tag_posteriors(&sentence).unwrap().iter().zip(posteriors.iter()) {
+        assert!((m["BC"] - p["B"] - p["C"]).abs() < 1e-9);
+    }
+
+    // A merged tag is scored with the posteriors of every tag merged into it.
+    mapped.inner_mut().set_decoding_strategy(DecodingStrategy::Posterior);
+    let (tags, scores) = mapped.tag(&sentence);
+    let mapped_posteriors = mapped.tag_posteriors(&sentence).unwrap();
+    for ((tag, score), marginals) in tags.iter().zip(scores.unwrap()).zip(mapped_posteriors.iter()) {
+        assert!((score - marginals[tag]).abs() < 1e-9);
+    }
+    let by_vote = MappedTagger::new(Ensemble::new(vec![ConstantTagger("B".into())], Vote::Majority)?, mapping.clone());
+    assert_eq!(by_vote.tag(&sentence), (vec!["BC".to_string(); 3], None));
+    mapped.inner_mut().set_decoding_strategy(DecodingStrategy::Viterbi);
+
+    let unmapped = evaluate(mapped.inner(), "tests/data/mttest-dev.txt".into())?;
+    let merged = evaluate_mapped(&mapped, "tests/data/mttest-dev.txt".into(), &mapping)?;
+    assert!(merged >= unmapped);
+
+    Ok(())
+}
+
+#[test]
+fn test_suffix_model_tags_unknown_words() -> Result<(), Box<dyn Error>> {
+    let sentences = tagged_sentences(&[
+        "the=D cats=N were=V running=G",
+        "the=D dogs=N were=V walking=G",
+        "the=D birds=N were=V singing=G",
+        "the=D horses=N were=V eating=G",
+        "the=D running=G were=V dogs=N"
+    ]);
+
+    let model = POSTaggingHMMTrainer::new()
+        .with_suffix_length(3)
+        .train_sentences(sentences.clone())
+        .finalize()?;
+    assert_eq!(model.suffix_model().map(|s| s.max_length()), Some(3));
+
+    let reloaded = reload(&model)?;
+
+    let sentence: Vec<String> = vec!["the".into(), "zebras".into(), "were".into(), "jumping".into()];
+    let tags: Vec<String> = reloaded.predict(sentence).into_iter().map(|(_, t)| t).collect();
+    assert_eq!(tags, vec!["D", "N", "V", "G"]);
+
+    let posteriors = reloaded.tag_posteriors(&["snowing".to_string()]).unwrap();
+    assert!(posteriors[0]["G"] > 0.5);
+
+    // Without any rare words the suffix model knows no tag, so unknown words
+    // fall back to their pseudo-words.
+    let model = POSTaggingHMMTrainer::new()
+        .with_suffix_length(3)
+        .with_rare_word_threshold(0)
+        .train_sentences(sentences)
+        .finalize()?;
+    let posteriors = model.tag_posteriors(&["the".to_string(), "zebras".to_string()]).unwrap();
+    assert!(posteriors.iter().all(|p| (p.values().sum::<f64>() - 1.0).abs() < 1e-9));
+
+    Ok(())
+}
+
+#[test]
+fn test_suffix_model_improves_unknown_word_accuracy() -> Result<(), Box<dyn Error>> {
+    let words = [
+        "sanity=N", "purity=N", "clarity=N", "vanity=N", "arson=N", "parson=N",
+        "Jackson=P", "Carlson=P", "Dawson=P", "Nelson=P"
+    ];
+    let sentences: Vec<String> = words.iter().map(|w| format!("she=O likes=V {w} .=.")).collect();
+    let sentences: Vec<&str> = sentences.iter().map(String::as_str).collect();
+
+    // Every word is unknown. "poison" shares its pseudo-word with the names,
+    // but only lowercase words ending in "son" are nouns.
+    let unknown = [("charity", "N"), ("rarity", "N"), ("poison", "N"), ("Wilson", "P"), ("Hudson", "P")];
+    let accuracy = |model: &POSTaggingHMM| {
+        let correct = unknown
+            .iter()
+            .filter(|(word, tag)| {
+                let sentence: Vec<String> = ["she", "likes", word, "."].map(String::from).to_vec();
+                model.tag(&sentence).0[2] == *tag
+            })
+            .count();
+        correct as f64 / unknown.len() as f64
+    };
+
+    let pseudo_words = POSTaggingHMMTrainer::new()
+        .train_sentences(tagged_sentences(&sentences))
+        .finalize()?;
+    let suffixes = POSTaggingHMMTrainer::new()
+        .with_suffix_length(3)
+        .train_sentences(tagged_sentences(&sentences))
+        .finalize()?;
+    assert_eq!(accuracy(&pseudo_words), 0.8);
+    assert_eq!(accuracy(&suffixes), 1.0);
+
+    Ok(())
+}
+
+#[test]
+fn test_learned_affixes_classify_unknown_words() -> Result<(), Box<dyn Error>> {
+    let sentences = tagged_sentences(&[
+        "the=D cats=N were=V running=G",
+        "the=D dogs=N were=V walking=G",
+        "the=D birds=N were=V singing=G",
+        "the=D horses=N were=V eating=G",
+        "the=D smiling=G were=V dogs=N"
+    ]);
+
+    let model = POSTaggingHMMTrainer::new()
+        .with_learned_affixes(2)
+        .train_sentences(sentences)
+        .finalize()?;
+
+    let classes = model.affix_classes().unwrap();
+    let affixes: Vec<&Affix> = classes.affixes().collect();
+    assert_eq!(affixes, vec![&Affix::Suffix("g".into())]);
+    assert_eq!(classes.matching_artificial_tag("jumping"), Some("SUFF-G"));
+    assert_eq!(classes.matching_artificial_tag("1999"), Some("IS-A-NUMBER"));
+
+    let sentence: Vec<String> = vec!["the".into(), "zebras".into(), "were".into(), "jumping".into()];
+    let tags: Vec<String> = model.predict(sentence).into_iter().map(|(_, t)| t).collect();
+    assert_eq!(tags, vec!["D", "N", "V", "G"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_case_sensitive_models_use_capitalization() -> Result<(), Box<dyn Error>> {
+    let sentences = tagged_sentences(&[
+        "they=PRP visited=V us=PRP .=.",
+        "they=PRP visited=V us=PRP .=.",
+        "they=PRP visited=V them=PRP .=.",
+        "they=PRP visited=V US=NNP .=.",
+        "they=PRP visited=V Rome=NNP .=.",
+        "they=PRP visited=V Berlin=NNP .=.",
+        "They=PRP visited=V Oslo=NNP .=."
+    ]);
+
+    let tag = |model: &POSTaggingHMM, word: &str| {
+        let sentence: Vec<String> = vec!["they".into(), "visited".into(), word.into(), ".".into()];
+        model.predict(sentence).swap_remove(2)
+    };
+
+    let folded = POSTaggingHMMTrainer::new()
+        .train_sentences(sentences.clone())
+        .finalize()?;
+    assert!(!folded.is_case_sensitive());
+    assert_eq!(tag(&folded, "US"), ("US".to_string(), "PRP".to_string()));
+
+    let model = reload(&POSTaggingHMMTrainer::new()
+        .with_case_sensitivity(true)
+        .with_capitalization_features(true)
+        .train_sentences(sentences.clone())
+        .finalize()?)?;
+
+    assert!(model.is_case_sensitive());
+    assert!(model.capitalization_model().is_some());
+    assert_eq!(tag(&model, "US").1, "NNP");
+    assert_eq!(tag(&model, "us").1, "PRP");
+    assert_eq!(tag(&model, "Paris").1, "NNP");
+    assert_eq!(tag(&model, "everyone").1, "PRP");
+
+    // Known words already tell their capitalizations apart.
+    let uncapitalized = POSTaggingHMMTrainer::new()
+        .with_case_sensitivity(true)
+        .train_sentences(sentences)
+        .finalize()?;
+    let sentence: Vec<String> = vec!["They".into(), "visited".into(), "US".into()];
+    let expected = uncapitalized.tag_posteriors(&sentence).unwrap();
+    let actual = model.tag_posteriors(&sentence).unwrap();
+    for (a, e) in actual.iter().zip(expected.iter()) {
+        assert!(a.iter().all(|(tag, p)| (p - e[tag]).abs() < 1e-9));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_word_class_rules_are_saved_with_the_model() -> Result<(), Box<dyn Error>> {
+    let rules_file = TempFile::new("word-classes.txt");
+    std::fs::write(&rules_file, "# Links and mentions\nIS-A-URL /^https?://\\S+$/\nIS-A-MENTION prefix:@\n")?;
+    let rules = WordClassRules::from_file(rules_file.path())?;
+    assert_eq!(rules.len(), 2);
+    assert_eq!(rules.matching_class("https://example.com"), Some("IS-A-URL"));
+    assert_eq!(rules.matching_class("@someone"), Some("IS-A-MENTION"));
+    assert_eq!(rules.matching_class("running"), None);
+
+    let sentences = tagged_sentences(&[
+        "see=V https://a.org=L now=R",
+        "see=V http://b.net=L now=R",
+        "ask=V @ann=M now=R",
+        "ask=V @bob=M now=R",
+        "see=V @cat=M now=R"
+    ]);
+
+    let model = reload(&POSTaggingHMMTrainer::new()
+        .with_word_class_rules(rules)
+        .train_sentences(sentences)
+        .finalize()?)?;
+    assert_eq!(model.word_class_rules().map(WordClassRules::len), Some(2));
+
+    for (word, expected) in [("https://c.com", "L"), ("@dan", "M")] {
+        let sentence: Vec<String> = vec!["see".into(), word.into(), "now".into()];
+        assert_eq!(model.predict(sentence)[1].1, expected);
+    }
+
+    let shipped = WordClassRules::from_file("data/unknown-word-rules.txt".into())?;
+    assert_eq!(shipped.matching_class("«"), Some("IS-PUNCTUATION"));
+    assert_eq!(pos_tagger::nlp::get_matching_artificial_tag("«"), None);
+
+    std::fs::write(&rules_file, "IS-A-URL /(/\n")?;
+    assert!(WordClassRules::from_file(rules_file.path()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_unicode_normalization_applies_when_tagging() -> Result<(), Box<dyn Error>> {
+    let sentences = tagged_sentences(&[
+        "ist=V Straße=N .=.",
+        "ist=V Café=N .=.",
+        "ist=V schön=A .=.",
+        "ist=V groß=A .=.",
+        "ist=V klein=A .=.",
+        "ist=V lang=A .=."
+    ]);
+
+    let tag = |model: &POSTaggingHMM, word: &str| {
+        let sentence: Vec<String> = vec!["ist".into(), word.into(), ".".into()];
+        model.predict(sentence)[1].1.clone()
+    };
+    let posteriors = |model: &POSTaggingHMM, word: &str| {
+        let sentence: Vec<String> = vec!["ist".into(), word.into(), ".".into()];
+        model.posteriors(sentence).swap_remove(1).1
+    };
+
+    let plain = POSTaggingHMMTrainer::new()
+        .train_sentences(sentences.clone())
+        .finalize()?;
+    assert_eq!(tag(&plain, "STRASSE"), "N");
+    assert_ne!(posteriors(&plain, "Cafe\u{301}"), posteriors(&plain, "Café"));
+
+    let model = reload(&POSTaggingHMMTrainer::new()
+        .with_normalization_form(pos_tagger::nlp::NormalizationForm::Nfc)
+        .train_sentences(sentences.clone())
+        .finalize()?)?;
+
+    assert_eq!(model.normalizer().form, Some(pos_tagger::nlp::NormalizationForm::Nfc));
+    assert_eq!(tag(&model, "STRASSE"), "N");
+    assert_eq!(posteriors(&model, "Cafe\u{301}"), posteriors(&model, "Café"));
+    assert_eq!(tag(&model, "SCHÖN"), "A");
+
+    let crf = pos_tagger::crf::CRFTrainer::new()
+        .with_normalization_form(pos_tagger::nlp::NormalizationForm::Nfc)
+        .train_sentences(sentences.clone())
+        .finalize()?;
+    let file = TempFile::new("normalized.crf");
+    crf.save(file.path())?;
+    let crf = pos_tagger::crf::CRFTagger::from_file(file.path())?;
+    let crf_posteriors = |word: &str| crf.posteriors(vec!["ist".into(), word.into(), ".".into()]).swap_remove(1).1;
+    assert_eq!(crf.normalizer().form, Some(pos_tagger::nlp::NormalizationForm::Nfc));
+    assert_eq!(crf_posteriors("Cafe\u{301}"), crf_posteriors("Café"));
+
+    let perceptron = pos_tagger::perceptron::PerceptronTrainer::new()
+        .with_case_sensitivity(true)
+        .train_sentences(sentences)
+        .finalize()?;
+    assert_eq!(perceptron.normalizer(), Normalizer { form: None, case_folding: CaseFolding::None });
+
+    Ok(())
+}
+
+#[test]
+fn test_smoothing_strategies() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::{Smoothing, StringCounter, StringFrequencyDistribution};
+
+    let mut counter = StringCounter::new();
+    for (key, count) in [("a", 1), ("b", 1), ("c", 1), ("d", 2), ("e", 3), ("f", 7)] {
+        counter.increment_by(key, count);
+    }
+
+    for smoothing in ["witten-bell", "good-turing", "absolute:0.75"] {
+        let smoothing: Smoothing = smoothing.parse()?;
+        let distribution = StringFrequencyDistribution::smoothed(counter.clone().into_weights(), smoothing, 100, None);
+        let seen: f64 = distribution.values().map(|p| p.exp()).sum();
+        let total = seen + 100.0 * distribution.smoothed_default().exp();
+
+        assert!((total - 1.0).abs() < 1e-9, "{smoothing} sums to {total}");
+        assert!(distribution.get_likelihood("f") > distribution.get_likelihood("a"));
+        assert!(distribution.get_likelihood("a") > distribution.get_likelihood("z"));
+
+        // Without counts, all the probability goes to the unseen keys.
+        let empty = StringFrequencyDistribution::smoothed(Vec::new(), smoothing, 4, None);
+        assert_eq!(empty.keys().count(), 0);
+        assert!((empty.smoothed_default() - 0.25f64.ln()).abs() < 1e-12, "{smoothing}");
+        let empty = StringFrequencyDistribution::smoothed(Vec::new(), smoothing, 4, Some(&distribution));
+        for key in ["a", "f", "z"] {
+            assert!((empty.get_likelihood(key) - distribution.get_likelihood(key)).abs() < 1e-12, "{smoothing}");
+        }
+    }
+
+    assert_eq!("laplace".parse::<Smoothing>()?, Smoothing::Lidstone(1.0));
+    assert_eq!("absolute:0.75".parse::<Smoothing>()?, Smoothing::AbsoluteDiscounting(0.75));
+    assert!("absolute:1.5".parse::<Smoothing>().is_err());
+    assert!("kneser-ney".parse::<Smoothing>().is_err());
+
+    let model = POSTaggingHMMTrainer::new()
+        .with_smoothing(Smoothing::GoodTuring)
+        .with_transition_smoothing(Smoothing::WittenBell)
+        .with_emission_smoothing(Smoothing::AbsoluteDiscounting(0.5))
+        .train("tests/data/mttest-training.txt".into())?
+        .finalize()?;
+    let sentence: Vec<String> = vec!["the".into(), "dog".into(), "runs".into()];
+    assert_eq!(model.predict(sentence.clone()).len(), 3);
+
+    let trigram_posteriors = |smoothing: Smoothing| -> Result<_, Box<dyn Error>> {
+        let model = POSTaggingHMMTrainer::new()
+            .with_order(HMMOrder::Second)
+            .with_transition_smoothing(smoothing)
+            .train_sentences(tagged_sentences(&[
+                "a=A b=B c=C a=A", "a=A c=C b=B", "b=B a=A c=C b=B", "c=C a=A b=B",
+                "a=A a=A b=B", "b=B b=B c=C", "c=C b=B a=A a=A"
+            ]))
+            .finalize()?;
+        Ok(model.tag_posteriors(&["a".into(), "b".into()]).unwrap())
+    };
+    assert_ne!(trigram_posteriors(Smoothing::default())?, trigram_posteriors(Smoothing::WittenBell)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_tuning_ranks_candidates_on_a_dev_file() -> Result<(), Box<dyn Error>> {
+    use pos_tagger::{Smoothing, tagset::TagMapping};
+
+    let trainer = POSTaggingHMMTrainer::new()
+        .train("tests/data/mttest-training.txt".into())?;
+    let smoothings = vec![Smoothing::Lidstone(1e-5), Smoothing::Lidstone(0.1), Smoothing::WittenBell];
+
+    let grid = search_candidates(&smoothings, &[1, 2], Search::Grid);
+    assert_eq!(grid.len(), 6);
+    assert_eq!(grid[1], Hyperparameters { smoothing: Smoothing::Lidstone(1e-5), rare_word_threshold: 2 });
+
+    let random = search_candidates(&smoothings, &[1, 3], Search::Random { trials: 10, seed: 7 });
+    assert_eq!(random.len(), 10);
+    assert_eq!(random, search_candidates(&smoothings, &[1, 3], Search::Random { trials: 10, seed: 7 }));
+    for candidate in random.iter() {
+        assert!((1..=3).contains(&candidate.rare_word_threshold));
+        if let Smoothing::Lidstone(alpha) = candidate.smoothing {
+            assert!((1e-5..=0.1).contains(&alpha));
+        }
+    }
+
+    let mut tried = 0;
+    let no_mapping = TagMapping::default();
+    let (ranking, model) = tune_hyperparameters(&trainer, grid, "tests/data/mttest-dev.txt".into(), &no_mapping, |_, _| {
+        tried += 1;
+    })?;
+    assert_eq!(tried, 6);
+    assert_eq!(ranking.len(), 6);
+    assert!(ranking.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+    let best = ranking[0].0;
+    let expected = trainer
+        .clone()
+        .with_smoothing(best.smoothing)
+        .with_rare_word_threshold(best.rare_word_threshold)
+        .finalize()?;
+    let sentence: Vec<String> = vec!["the".into(), "dog".into(), "runs".into()];
+    assert_eq!(model.predict(sentence.clone()), expected.predict(sentence));
+
+    assert!(tune_hyperparameters(&trainer, Vec::new(), "tests/data/mttest-dev.txt".into(), &no_mapping, |_, _| ()).is_err());
+
+    let file = TempFile::new("unknown-tags.txt");
+    std::fs::write(&file, "A X\nB X\nC X\nD X\n")?;
+    let unknown_tags = TagMapping::from_file(file.path())?;
+    let candidates = search_candidates(&smoothings, &[1], Search::Grid);
+    let (ranking, _) = tune_hyperparameters(&trainer, candidates, "tests/data/mttest-dev.txt".into(), &unknown_tags, |_, _| ())?;
+    assert!(ranking.iter().all(|&(_, accuracy)| accuracy == 0.0));
+
+    Ok(())
+}